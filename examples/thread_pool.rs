@@ -0,0 +1,101 @@
+//! A small work-stealing thread pool that sums the squares of a range by
+//! splitting it in halves recursively.
+//!
+//! Each thread runs the tasks of its own `Worker` deque, newest first,
+//! and steals the oldest (and thus largest) tasks of the others when it
+//! runs out.
+//!
+//!     cargo run --release --example thread_pool
+
+use ads::linear::chase_lev::{Steal, Stealer, Worker};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+const THREADS: usize = 4;
+const LEAF_SIZE: u64 = 1_000;
+const END: u64 = 10_000_000;
+
+/// Sums the squares in a range, splitting it when it is too large.
+struct Task(Range<u64>);
+
+struct Shared {
+    stealers: Vec<Stealer<Task>>,
+    /// Tasks pushed but not finished yet: the pool is done at zero.
+    pending: AtomicUsize,
+    total: AtomicU64,
+}
+
+fn run(id: usize, worker: Worker<Task>, shared: &Shared) -> (usize, usize) {
+    let (mut executed, mut stolen) = (0, 0);
+    while shared.pending.load(Ordering::Acquire) > 0 {
+        let task = worker.pop().or_else(|| {
+            let task = steal(id, shared);
+            stolen += task.is_some() as usize;
+            task
+        });
+        let Task(range) = match task {
+            Some(task) => task,
+            None => {
+                thread::yield_now();
+                continue;
+            }
+        };
+        if range.end - range.start > LEAF_SIZE {
+            let middle = range.start + (range.end - range.start) / 2;
+            shared.pending.fetch_add(2, Ordering::AcqRel);
+            worker.push(Task(range.start..middle));
+            worker.push(Task(middle..range.end));
+        } else {
+            let sum: u64 = range.map(|i| i * i % 1_000_007).sum();
+            shared.total.fetch_add(sum, Ordering::Relaxed);
+        }
+        executed += 1;
+        shared.pending.fetch_sub(1, Ordering::AcqRel);
+    }
+    (executed, stolen)
+}
+
+/// Tries the other threads' deques once each, starting with the next one.
+fn steal(id: usize, shared: &Shared) -> Option<Task> {
+    let others = (1..THREADS).map(|offset| &shared.stealers[(id + offset) % THREADS]);
+    for stealer in others {
+        loop {
+            match stealer.steal() {
+                Steal::Success(task) => return Some(task),
+                Steal::Empty => break,
+                Steal::Retry => continue,
+            }
+        }
+    }
+    None
+}
+
+fn main() {
+    let workers: Vec<Worker<Task>> = (0..THREADS).map(|_| Worker::new()).collect();
+    let shared = Arc::new(Shared {
+        stealers: workers.iter().map(Worker::stealer).collect(),
+        pending: AtomicUsize::new(1),
+        total: AtomicU64::new(0),
+    });
+    workers[0].push(Task(0..END));
+
+    let threads: Vec<_> = workers
+        .into_iter()
+        .enumerate()
+        .map(|(id, worker)| {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run(id, worker, &shared))
+        })
+        .collect();
+    for (id, thread) in threads.into_iter().enumerate() {
+        let (executed, stolen) = thread.join().unwrap();
+        println!("thread {}: {} tasks, {} stolen", id, executed, stolen);
+    }
+
+    let expected: u64 = (0..END).map(|i| i * i % 1_000_007).sum();
+    let total = shared.total.load(Ordering::Relaxed);
+    println!("sum of squares mod 1000007 below {}: {}", END, total);
+    assert_eq!(total, expected);
+}
@@ -0,0 +1,341 @@
+use alloc_crate::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+/// Node allocator in the spirit of Brass 1.4: nodes are carved out of
+/// blocks of `block_size` slots instead of being allocated one by one,
+/// and freed slots are threaded into a free list to be handed out
+/// again before any new block is requested.
+///
+/// Nodes are addressed through typed `Handle`s rather than pointers,
+/// so linked structures built on a pool store handles as their links;
+/// `Stack` and `BinarySearchTree` each own one.
+///
+/// Blocks are kept when their nodes are freed, ready for the next
+/// nodes; `shrink` releases the empty blocks at the end.
+#[derive(Clone)]
+pub struct NodePool<N> {
+    blocks: Vec<Vec<Slot<N>>>,
+    nodes_per_block: Vec<usize>,
+    free: Option<usize>,
+    block_size: usize,
+    len: usize,
+}
+
+#[derive(Clone)]
+enum Slot<N> {
+    Occupied(N),
+    Free { next: Option<usize> },
+}
+
+/// Index of a node in a `NodePool<N>`.
+///
+/// A handle stays valid until its node is freed; after that the slot
+/// may be reused, and the handle will point to the new node.
+pub struct Handle<N> {
+    index: usize,
+    marker: PhantomData<fn() -> N>,
+}
+
+/// Counters describing the memory held by a `NodePool`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Blocks allocated and not released by `shrink`.
+    pub blocks: usize,
+    /// Blocks holding at least one live node.
+    pub blocks_in_use: usize,
+    /// Live nodes.
+    pub nodes_in_use: usize,
+    /// Slots ready to be reused without allocating.
+    pub free_nodes: usize,
+}
+
+const DEFAULT_BLOCK_SIZE: usize = 64;
+
+impl<N> NodePool<N> {
+    pub fn new() -> Self {
+        NodePool::with_block_size(DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Panics if `block_size` is zero.
+    pub fn with_block_size(block_size: usize) -> Self {
+        assert!(block_size > 0, "blocks must hold at least one node");
+        NodePool {
+            blocks: Vec::new(),
+            nodes_per_block: Vec::new(),
+            free: None,
+            block_size,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            blocks: self.blocks.len(),
+            blocks_in_use: self.nodes_per_block.iter().filter(|&&n| n > 0).count(),
+            nodes_in_use: self.len,
+            free_nodes: self.blocks.len() * self.block_size - self.len,
+        }
+    }
+
+    /// Stores `node` in a recycled slot if there is one, otherwise in
+    /// the last block, allocating a new block when that one is full.
+    pub fn alloc(&mut self, node: N) -> Handle<N> {
+        let index = match self.free {
+            Some(index) => {
+                match core::mem::replace(self.slot_mut(index), Slot::Occupied(node)) {
+                    Slot::Free { next } => self.free = next,
+                    Slot::Occupied(_) => unreachable!("occupied slot in the free list"),
+                }
+                index
+            }
+            None => {
+                // With an empty free list, every handed out slot is live.
+                if self.len == self.blocks.len() * self.block_size {
+                    self.blocks.push(Vec::with_capacity(self.block_size));
+                    self.nodes_per_block.push(0);
+                }
+                let block_index = self.blocks.len() - 1;
+                let block = &mut self.blocks[block_index];
+                block.push(Slot::Occupied(node));
+                block_index * self.block_size + block.len() - 1
+            }
+        };
+        self.nodes_per_block[index / self.block_size] += 1;
+        self.len += 1;
+        Handle {
+            index,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the node and puts its slot on the free list.
+    ///
+    /// Panics if the node was already freed.
+    pub fn free(&mut self, handle: Handle<N>) -> N {
+        let next = self.free;
+        let slot = self.slot_mut(handle.index);
+        if let Slot::Free { .. } = slot {
+            panic!("node freed twice");
+        }
+        let node = match core::mem::replace(slot, Slot::Free { next }) {
+            Slot::Occupied(node) => node,
+            Slot::Free { .. } => unreachable!(),
+        };
+        self.free = Some(handle.index);
+        self.nodes_per_block[handle.index / self.block_size] -= 1;
+        self.len -= 1;
+        node
+    }
+
+    /// Panics if the node was freed.
+    pub fn get(&self, handle: Handle<N>) -> &N {
+        let block = &self.blocks[handle.index / self.block_size];
+        match &block[handle.index % self.block_size] {
+            Slot::Occupied(node) => node,
+            Slot::Free { .. } => panic!("use of a freed node"),
+        }
+    }
+
+    /// Panics if the node was freed.
+    pub fn get_mut(&mut self, handle: Handle<N>) -> &mut N {
+        match self.slot_mut(handle.index) {
+            Slot::Occupied(node) => node,
+            Slot::Free { .. } => panic!("use of a freed node"),
+        }
+    }
+
+    /// Pointer to the node, for structures that hand out references to
+    /// several of their nodes at once. It is derived from the node's
+    /// slot alone, so pointers to other nodes taken the same way stay
+    /// valid; it dangles once the node is freed.
+    ///
+    /// Panics if the node was freed.
+    pub fn get_ptr(&mut self, handle: Handle<N>) -> *mut N {
+        let block = &mut self.blocks[handle.index / self.block_size];
+        let offset = handle.index % self.block_size;
+        assert!(offset < block.len(), "handle out of bounds");
+        // SAFETY: `offset` is within the block's slots, and borrowing the
+        // slot through the block's pointer borrows nothing else.
+        match unsafe { &mut *block.as_mut_ptr().add(offset) } {
+            Slot::Occupied(node) => node,
+            Slot::Free { .. } => panic!("use of a freed node"),
+        }
+    }
+
+    /// Releases the empty blocks after the last block in use. Blocks
+    /// before it stay, as handles encode the block they point into.
+    pub fn shrink(&mut self) {
+        let mut keep = self.blocks.len();
+        while keep > 0 && self.nodes_per_block[keep - 1] == 0 {
+            keep -= 1;
+        }
+        if keep == self.blocks.len() {
+            return;
+        }
+        // Drop the released slots from the free list, keeping the order
+        // of the others.
+        let limit = keep * self.block_size;
+        let mut link = self.free.take();
+        let mut tail: Option<usize> = None;
+        while let Some(index) = link {
+            link = match self.slot_mut(index) {
+                Slot::Free { next } => *next,
+                Slot::Occupied(_) => unreachable!("occupied slot in the free list"),
+            };
+            if index < limit {
+                match tail {
+                    None => self.free = Some(index),
+                    Some(tail) => *self.slot_mut(tail) = Slot::Free { next: Some(index) },
+                }
+                tail = Some(index);
+            }
+        }
+        if let Some(tail) = tail {
+            *self.slot_mut(tail) = Slot::Free { next: None };
+        }
+        self.blocks.truncate(keep);
+        self.nodes_per_block.truncate(keep);
+    }
+
+    fn slot_mut(&mut self, index: usize) -> &mut Slot<N> {
+        &mut self.blocks[index / self.block_size][index % self.block_size]
+    }
+}
+
+impl<N> Default for NodePool<N> {
+    fn default() -> Self {
+        NodePool::new()
+    }
+}
+
+impl<N> Clone for Handle<N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<N> Copy for Handle<N> {}
+
+impl<N> PartialEq for Handle<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<N> Eq for Handle<N> {}
+
+impl<N> fmt::Debug for Handle<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Handle").field(&self.index).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_alloc_and_free() {
+        let mut pool: NodePool<&str> = NodePool::new();
+        let a = pool.alloc("a");
+        let b = pool.alloc("b");
+        assert_eq!(pool.len(), 2);
+        assert_eq!(*pool.get(a), "a");
+        *pool.get_mut(b) = "B";
+        assert_eq!(pool.free(b), "B");
+        assert_eq!(pool.free(a), "a");
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn test_recycles_freed_slots_before_allocating() {
+        let mut pool: NodePool<u32> = NodePool::with_block_size(4);
+        let handles: Vec<_> = (0..6).map(|i| pool.alloc(i)).collect();
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                blocks: 2,
+                blocks_in_use: 2,
+                nodes_in_use: 6,
+                free_nodes: 2,
+            }
+        );
+        for &handle in &handles[4..] {
+            pool.free(handle);
+        }
+        assert_eq!(pool.stats().blocks_in_use, 1);
+        let recycled = pool.alloc(10);
+        assert!(handles[4..].contains(&recycled));
+        assert_eq!(pool.stats().blocks, 2);
+        assert_eq!(pool.stats().free_nodes, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "node freed twice")]
+    fn test_double_free_panics() {
+        let mut pool: NodePool<u32> = NodePool::new();
+        let handle = pool.alloc(1);
+        pool.free(handle);
+        pool.free(handle);
+    }
+
+    #[test]
+    fn test_shrink_releases_trailing_empty_blocks() {
+        let mut pool: NodePool<u32> = NodePool::with_block_size(4);
+        let handles: Vec<_> = (0..12).map(|i| pool.alloc(i)).collect();
+        for &handle in handles[2..].iter().rev() {
+            pool.free(handle);
+        }
+        pool.shrink();
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                blocks: 1,
+                blocks_in_use: 1,
+                nodes_in_use: 2,
+                free_nodes: 2,
+            }
+        );
+        // The freed slots of the kept block are still reused first.
+        let recycled = [pool.alloc(20), pool.alloc(21)];
+        assert!(recycled.iter().all(|handle| handles[2..4].contains(handle)));
+        pool.alloc(22);
+        assert_eq!(pool.stats().blocks, 2);
+        assert_eq!(*pool.get(handles[1]), 1);
+    }
+
+    #[test]
+    fn test_linked_list_of_handles() {
+        struct Node {
+            element: u32,
+            next: Option<Handle<Node>>,
+        }
+        let mut pool = NodePool::with_block_size(8);
+        let mut head = None;
+        for element in 0..100 {
+            head = Some(pool.alloc(Node {
+                element,
+                next: head,
+            }));
+        }
+        assert_eq!(pool.stats().blocks, 13);
+        let mut sum = 0;
+        while let Some(handle) = head {
+            let node = pool.free(handle);
+            sum += node.element;
+            head = node.next;
+        }
+        assert_eq!(sum, 4950);
+        assert_eq!(pool.stats().blocks_in_use, 0);
+        assert_eq!(pool.stats().free_nodes, 104);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::hazard::Domain;
+use alloc_crate::boxed::Box;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// Lock-free queue (Michael and Scott, 1996).
+///
+/// The list always starts with a dummy node: `head` points to it and
+/// the front element lives in the node after it. Dequeuing swings
+/// `head` to that node, which becomes the new dummy once its element is
+/// moved out. Enqueuing links a node after the last one and then swings
+/// `tail`; since those are two steps, any thread that finds `tail`
+/// lagging behind helps move it forward before retrying.
+///
+/// Unlinked nodes are retired to a hazard pointer domain, like in
+/// `AtomicStack`.
+pub struct AtomicQueue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+    domain: Domain,
+    marker: PhantomData<T>,
+}
+
+/// The element is uninitialized in the dummy node, including once it
+/// has been moved out by the thread that made the node the dummy.
+struct Node<T> {
+    element: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new(element: MaybeUninit<T>) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            element,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+impl<T> AtomicQueue<T> {
+    pub fn new() -> Self {
+        let dummy = Node::new(MaybeUninit::uninit());
+        AtomicQueue {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+            domain: Domain::new(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let guard = self.domain.guard();
+        let head = guard.protect(0, &self.head);
+        // SAFETY: `head` is protected, so it has not been reclaimed.
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+
+    pub fn enqueue(&self, element: T) {
+        let node = Node::new(MaybeUninit::new(element));
+        let guard = self.domain.guard();
+        loop {
+            let tail = guard.protect(0, &self.tail);
+            // SAFETY: `tail` is protected, so it has not been reclaimed.
+            let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+            if !next.is_null() {
+                // `tail` is lagging behind: help the enqueuer that linked
+                // `next` and retry.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            // SAFETY: as above.
+            let linked = unsafe {
+                (*tail).next.compare_exchange(
+                    ptr::null_mut(),
+                    node,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+            };
+            if linked.is_ok() {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, node, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    pub fn dequeue(&self) -> Option<T> {
+        let guard = self.domain.guard();
+        loop {
+            let head = guard.protect(0, &self.head);
+            // SAFETY: `head` is protected, so it has not been reclaimed.
+            let next = guard.protect(1, unsafe { &(*head).next });
+            // `next` is only retired after `head` moves past it, so while
+            // `head` has not moved, protecting `next` was not too late.
+            if self.head.load(Ordering::Acquire) != head {
+                continue;
+            }
+            if next.is_null() {
+                return None;
+            }
+            let tail = self.tail.load(Ordering::Acquire);
+            if head == tail {
+                // Never let `head` overtake a lagging `tail`.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                // SAFETY: winning the exchange makes this thread the only
+                // one to take the element of `next`, which stays protected
+                // while it is read. The old dummy is now unlinked.
+                unsafe {
+                    let element = (*next).element.as_ptr().read();
+                    guard.retire(head);
+                    return Some(element);
+                }
+            }
+        }
+    }
+}
+
+impl<T> Default for AtomicQueue<T> {
+    fn default() -> Self {
+        AtomicQueue::new()
+    }
+}
+
+impl<T> Drop for AtomicQueue<T> {
+    fn drop(&mut self) {
+        // SAFETY: with `&mut self` the remaining nodes are ours alone; all
+        // of them but the dummy hold an element.
+        unsafe {
+            let dummy = Box::from_raw(*self.head.get_mut());
+            let mut node = dummy.next.load(Ordering::Relaxed);
+            while !node.is_null() {
+                let mut current = Box::from_raw(node);
+                current.element.as_mut_ptr().drop_in_place();
+                node = current.next.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+// SAFETY: elements are moved between threads but never shared.
+unsafe impl<T: Send> Send for AtomicQueue<T> {}
+unsafe impl<T: Send> Sync for AtomicQueue<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::DropCounter;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::thread;
+
+    const PRODUCERS: usize = 4;
+    const CONSUMERS: usize = 4;
+    const PER_PRODUCER: usize = 20_000;
+
+    #[test]
+    fn test_enqueue_and_dequeue_element() {
+        let q: AtomicQueue<i32> = AtomicQueue::new();
+        assert_eq!(q.dequeue(), None);
+        assert!(q.is_empty());
+        q.enqueue(2);
+        q.enqueue(20);
+        assert!(!q.is_empty());
+        assert_eq!(q.dequeue(), Some(2));
+        q.enqueue(2000);
+        assert_eq!(q.dequeue(), Some(20));
+        assert_eq!(q.dequeue(), Some(2000));
+        assert_eq!(q.dequeue(), None);
+    }
+
+    /// Every element is dequeued exactly once, and each consumer sees
+    /// the elements of any given producer in the order they were sent.
+    #[test]
+    fn test_multiple_producers_and_consumers() {
+        let q = Arc::new(AtomicQueue::new());
+        let consumed = Arc::new(AtomicUsize::new(0));
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let q = Arc::clone(&q);
+                thread::spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        q.enqueue((p, i));
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let q = Arc::clone(&q);
+                let consumed = Arc::clone(&consumed);
+                thread::spawn(move || {
+                    let mut received = Vec::new();
+                    let mut last = [None; PRODUCERS];
+                    while consumed.load(Ordering::Relaxed) < PRODUCERS * PER_PRODUCER {
+                        if let Some((p, i)) = q.dequeue() {
+                            consumed.fetch_add(1, Ordering::Relaxed);
+                            assert!(last[p] < Some(i), "out of order element");
+                            last[p] = Some(i);
+                            received.push((p, i));
+                        }
+                    }
+                    received
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let mut received: Vec<(usize, usize)> = consumers
+            .into_iter()
+            .flat_map(|consumer| consumer.join().unwrap())
+            .collect();
+        received.sort_unstable();
+        let expected: Vec<_> = (0..PRODUCERS)
+            .flat_map(|p| (0..PER_PRODUCER).map(move |i| (p, i)))
+            .collect();
+        assert_eq!(received, expected);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_drop_releases_elements() {
+        let counter = DropCounter::new();
+        {
+            let q = AtomicQueue::new();
+            for _ in 0..100 {
+                q.enqueue(counter.token());
+            }
+            for _ in 0..50 {
+                q.dequeue();
+            }
+            assert_eq!(counter.alive(), 50);
+        }
+        assert_eq!(counter.alive(), 0);
+    }
+}
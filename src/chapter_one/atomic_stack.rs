@@ -0,0 +1,212 @@
+use crate::hazard::Domain;
+use alloc_crate::boxed::Box;
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// Lock-free stack (Treiber, 1986): `push` and `pop` swing `head` with
+/// a compare-and-swap and retry if another thread got there first.
+///
+/// A popped node may still be read by threads that loaded `head` before
+/// the swap, so it is retired to a hazard pointer domain instead of
+/// being freed. Hazard pointers also rule out the ABA problem: a node
+/// cannot be freed and reallocated while a thread is about to swap it.
+pub struct AtomicStack<T> {
+    head: AtomicPtr<Node<T>>,
+    domain: Domain,
+    marker: PhantomData<T>,
+}
+
+/// The element is moved out by the thread that unlinks the node, so
+/// retired nodes must not drop it again.
+struct Node<T> {
+    element: ManuallyDrop<T>,
+    next: *mut Node<T>,
+}
+
+impl<T> AtomicStack<T> {
+    pub fn new() -> Self {
+        AtomicStack {
+            head: AtomicPtr::new(ptr::null_mut()),
+            domain: Domain::new(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+
+    pub fn push(&self, element: T) {
+        let node = Box::into_raw(Box::new(Node {
+            element: ManuallyDrop::new(element),
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // SAFETY: the node is not shared until the exchange succeeds.
+            unsafe { (*node).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = self.domain.guard();
+        loop {
+            let head = guard.protect(0, &self.head);
+            if head.is_null() {
+                return None;
+            }
+            // SAFETY: `head` is protected, so it has not been reclaimed.
+            let next = unsafe { (*head).next };
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                // SAFETY: winning the exchange makes this thread the only
+                // one to take the element, and the node is now unlinked.
+                unsafe {
+                    let element = ptr::read(&*(*head).element);
+                    guard.retire(head);
+                    return Some(element);
+                }
+            }
+        }
+    }
+}
+
+impl<T> Default for AtomicStack<T> {
+    fn default() -> Self {
+        AtomicStack::new()
+    }
+}
+
+impl<T> Drop for AtomicStack<T> {
+    fn drop(&mut self) {
+        let mut node = *self.head.get_mut();
+        while !node.is_null() {
+            // SAFETY: with `&mut self` the remaining nodes are ours alone.
+            let mut current = unsafe { Box::from_raw(node) };
+            unsafe { ManuallyDrop::drop(&mut current.element) };
+            node = current.next;
+        }
+    }
+}
+
+// SAFETY: elements are moved between threads but never shared.
+unsafe impl<T: Send> Send for AtomicStack<T> {}
+unsafe impl<T: Send> Sync for AtomicStack<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::DropCounter;
+    use std::sync::Arc;
+    use std::thread;
+
+    const THREADS: usize = 8;
+    const PER_THREAD: usize = 10_000;
+
+    #[test]
+    fn test_push_pop() {
+        let s: AtomicStack<i32> = AtomicStack::new();
+        assert_eq!(s.pop(), None);
+        s.push(1);
+        s.push(2);
+        assert!(!s.is_empty());
+        assert_eq!(s.pop(), Some(2));
+        assert_eq!(s.pop(), Some(1));
+        assert_eq!(s.pop(), None);
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_pushes_then_pops() {
+        let s = Arc::new(AtomicStack::new());
+        let pushers: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let s = Arc::clone(&s);
+                thread::spawn(move || {
+                    for i in 0..PER_THREAD {
+                        s.push(t * PER_THREAD + i);
+                    }
+                })
+            })
+            .collect();
+        for pusher in pushers {
+            pusher.join().unwrap();
+        }
+        let poppers: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let s = Arc::clone(&s);
+                thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    while let Some(element) = s.pop() {
+                        popped.push(element);
+                    }
+                    popped
+                })
+            })
+            .collect();
+        let mut popped: Vec<usize> = poppers
+            .into_iter()
+            .flat_map(|popper| popper.join().unwrap())
+            .collect();
+        popped.sort_unstable();
+        assert_eq!(popped, (0..THREADS * PER_THREAD).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_interleaved_pushes_and_pops() {
+        let s = Arc::new(AtomicStack::new());
+        let workers: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let s = Arc::clone(&s);
+                thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    for i in 0..PER_THREAD {
+                        s.push(t * PER_THREAD + i);
+                        if i % 2 == 1 {
+                            popped.extend(s.pop());
+                            popped.extend(s.pop());
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+        let mut popped: Vec<usize> = workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect();
+        while let Some(element) = s.pop() {
+            popped.push(element);
+        }
+        popped.sort_unstable();
+        assert_eq!(popped, (0..THREADS * PER_THREAD).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_drop_releases_elements() {
+        let counter = DropCounter::new();
+        {
+            let s = AtomicStack::new();
+            for _ in 0..100 {
+                s.push(counter.token());
+            }
+            for _ in 0..50 {
+                s.pop();
+            }
+            assert_eq!(counter.alive(), 50);
+        }
+        assert_eq!(counter.alive(), 0);
+    }
+}
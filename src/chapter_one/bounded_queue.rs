@@ -0,0 +1,298 @@
+use super::queue::Queue;
+#[cfg(feature = "std")]
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// What a bounded queue does with an element enqueued while it is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Refuse the element and hand it back.
+    Reject,
+    /// Wait until a consumer makes room. Only `SyncBoundedQueue` can
+    /// wait; `BoundedQueue` rejects the element instead.
+    Block,
+    /// Dequeue the oldest element to make room.
+    OverwriteOldest,
+    /// Drop the element being enqueued, leaving the queue unchanged.
+    DropNewest,
+}
+
+/// `Queue` that never holds more than `capacity` elements.
+///
+/// `enqueue` returns `Ok(None)` when the element was simply added,
+/// `Ok(Some(dropped))` when the policy dropped an element (the oldest
+/// one, or the new one itself), and `Err(element)` when it was refused.
+pub struct BoundedQueue<T> {
+    queue: Queue<T>,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+impl<T> BoundedQueue<T> {
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        assert!(capacity > 0, "bounded queue must hold at least one element");
+        BoundedQueue {
+            queue: Queue::with_min_capacity(capacity),
+            capacity,
+            policy,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.queue.len() == self.capacity
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    pub fn enqueue(&mut self, element: T) -> Result<Option<T>, T> {
+        if !self.is_full() {
+            self.queue.enqueue(element);
+            return Ok(None);
+        }
+        match self.policy {
+            OverflowPolicy::Reject | OverflowPolicy::Block => Err(element),
+            OverflowPolicy::OverwriteOldest => {
+                let oldest = self.queue.dequeue();
+                self.queue.enqueue(element);
+                Ok(oldest)
+            }
+            OverflowPolicy::DropNewest => Ok(Some(element)),
+        }
+    }
+
+    pub fn dequeue(&mut self) -> Option<T> {
+        self.queue.dequeue()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.queue.peek()
+    }
+}
+
+/// Thread-safe `BoundedQueue`: producers `send` and consumers `recv`,
+/// waiting on condition variables when the queue is empty or, under
+/// `OverflowPolicy::Block`, full.
+#[cfg(feature = "std")]
+pub struct SyncBoundedQueue<T> {
+    queue: Mutex<BoundedQueue<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+#[cfg(feature = "std")]
+impl<T> SyncBoundedQueue<T> {
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        SyncBoundedQueue {
+            queue: Mutex::new(BoundedQueue::new(capacity, policy)),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity()
+    }
+
+    /// Enqueues `element` according to the policy, waiting for as long
+    /// as it takes under `OverflowPolicy::Block`.
+    pub fn send(&self, element: T) -> Result<Option<T>, T> {
+        let mut queue = self.lock();
+        if queue.policy() == OverflowPolicy::Block {
+            queue = self
+                .not_full
+                .wait_while(queue, |queue| queue.is_full())
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        self.enqueue(queue, element)
+    }
+
+    /// Like `send`, but gives the element back if the queue is still
+    /// full after `timeout` under `OverflowPolicy::Block`.
+    pub fn send_timeout(&self, element: T, timeout: Duration) -> Result<Option<T>, T> {
+        let mut queue = self.lock();
+        if queue.policy() == OverflowPolicy::Block {
+            let deadline = Instant::now() + timeout;
+            while queue.is_full() {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(element);
+                }
+                queue = self
+                    .not_full
+                    .wait_timeout(queue, deadline - now)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+            }
+        }
+        self.enqueue(queue, element)
+    }
+
+    /// Like `send`, but never waits: under `OverflowPolicy::Block` a
+    /// full queue rejects the element.
+    pub fn try_send(&self, element: T) -> Result<Option<T>, T> {
+        self.enqueue(self.lock(), element)
+    }
+
+    /// Dequeues the oldest element, waiting for one if the queue is
+    /// empty.
+    pub fn recv(&self) -> T {
+        let queue = self
+            .not_empty
+            .wait_while(self.lock(), |queue| queue.is_empty())
+            .unwrap_or_else(PoisonError::into_inner);
+        self.dequeue(queue)
+            .expect("queue is not empty after waiting")
+    }
+
+    /// Like `recv`, but gives up after `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+        let (queue, _) = self
+            .not_empty
+            .wait_timeout_while(self.lock(), timeout, |queue| queue.is_empty())
+            .unwrap_or_else(PoisonError::into_inner);
+        self.dequeue(queue)
+    }
+
+    pub fn try_recv(&self) -> Option<T> {
+        self.dequeue(self.lock())
+    }
+
+    /// The queue is never left half-updated, so a panic in another
+    /// thread holding the lock does not invalidate it.
+    fn lock(&self) -> MutexGuard<'_, BoundedQueue<T>> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn enqueue(
+        &self,
+        mut queue: MutexGuard<'_, BoundedQueue<T>>,
+        element: T,
+    ) -> Result<Option<T>, T> {
+        // Under `DropNewest` a full queue is left as it was.
+        let enqueued = !queue.is_full() || queue.policy() == OverflowPolicy::OverwriteOldest;
+        let result = queue.enqueue(element);
+        drop(queue);
+        if enqueued && result.is_ok() {
+            self.not_empty.notify_one();
+        }
+        result
+    }
+
+    fn dequeue(&self, mut queue: MutexGuard<'_, BoundedQueue<T>>) -> Option<T> {
+        let element = queue.dequeue();
+        drop(queue);
+        if element.is_some() {
+            self.not_full.notify_one();
+        }
+        element
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "std")]
+    use std::sync::Arc;
+    #[cfg(feature = "std")]
+    use std::thread;
+
+    fn fill(policy: OverflowPolicy) -> BoundedQueue<i32> {
+        let mut q = BoundedQueue::new(3, policy);
+        for i in 0..3 {
+            assert_eq!(q.enqueue(i), Ok(None));
+        }
+        assert!(q.is_full());
+        q
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let mut q = fill(OverflowPolicy::Reject);
+        assert_eq!(q.enqueue(3), Err(3));
+        assert_eq!(q.dequeue(), Some(0));
+        assert_eq!(q.enqueue(3), Ok(None));
+
+        let mut q = fill(OverflowPolicy::Block);
+        assert_eq!(q.enqueue(3), Err(3));
+
+        let mut q = fill(OverflowPolicy::OverwriteOldest);
+        assert_eq!(q.enqueue(3), Ok(Some(0)));
+        assert_eq!(q.peek(), Some(&1));
+        assert_eq!(q.len(), 3);
+
+        let mut q = fill(OverflowPolicy::DropNewest);
+        assert_eq!(q.enqueue(3), Ok(Some(3)));
+        assert_eq!(q.peek(), Some(&0));
+        assert_eq!(q.len(), 3);
+    }
+
+    #[test]
+    fn test_buffer_is_never_reallocated() {
+        let mut q = BoundedQueue::new(100, OverflowPolicy::OverwriteOldest);
+        let capacity = q.queue.capacity();
+        for round in 0..3 {
+            for i in 0..150 {
+                q.enqueue(i).unwrap();
+            }
+            while q.dequeue().is_some() {}
+            assert_eq!(q.queue.capacity(), capacity, "round {}", round);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_blocking_send_applies_backpressure() {
+        let q = Arc::new(SyncBoundedQueue::new(4, OverflowPolicy::Block));
+        let producer = {
+            let q = Arc::clone(&q);
+            thread::spawn(move || {
+                for i in 0..1_000 {
+                    assert_eq!(q.send(i), Ok(None));
+                    assert!(q.len() <= 4);
+                }
+            })
+        };
+        for i in 0..1_000 {
+            assert_eq!(q.recv(), i);
+        }
+        producer.join().unwrap();
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_timeouts() {
+        let q = SyncBoundedQueue::new(1, OverflowPolicy::Block);
+        assert_eq!(q.recv_timeout(Duration::from_millis(10)), None);
+        assert_eq!(q.send_timeout(1, Duration::from_millis(10)), Ok(None));
+        assert_eq!(q.send_timeout(2, Duration::from_millis(10)), Err(2));
+        assert_eq!(q.try_send(2), Err(2));
+        assert_eq!(q.recv_timeout(Duration::from_millis(10)), Some(1));
+        assert_eq!(q.try_recv(), None);
+    }
+}
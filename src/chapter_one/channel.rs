@@ -0,0 +1,581 @@
+use super::queue::Queue;
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Channel holding at most `capacity` messages: `send` waits for room.
+///
+/// Panics if `capacity` is zero.
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel must hold at least one message");
+    channel(Some(capacity))
+}
+
+/// Channel whose `send` never waits.
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    channel(None)
+}
+
+fn channel<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            messages: Queue::new(),
+            capacity,
+            senders: 1,
+            receivers: 1,
+            waiting_senders: Waiters::default(),
+            waiting_receivers: Waiters::default(),
+        }),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+/// Sending half; clone it for more producers.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Receiving half; clone it for more consumers. Each message goes to
+/// exactly one of them.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The message could not be sent because every `Receiver` is gone.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Closed(T),
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+}
+
+/// Tasks waiting for room or for a message park their wakers in
+/// `waiting_senders` and `waiting_receivers`. Each message sent wakes
+/// one receiver and each message received wakes one sender; only
+/// closing the channel wakes them all. A future dropped after it was
+/// woken passes the wakeup on to the next task, so it is not lost with
+/// it.
+struct State<T> {
+    messages: Queue<T>,
+    capacity: Option<usize>,
+    senders: usize,
+    receivers: usize,
+    waiting_senders: Waiters,
+    waiting_receivers: Waiters,
+}
+
+impl<T> State<T> {
+    fn is_full(&self) -> bool {
+        self.capacity == Some(self.messages.len())
+    }
+}
+
+impl<T> Shared<T> {
+    /// Every change under the lock is a single map operation or counter
+    /// update, and the only foreign code run there is cloning, comparing
+    /// and dropping wakers, before anything changes. A panic can poison
+    /// the lock but not leave the state inconsistent.
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Parked wakers keyed by a token handed to the future that parked
+/// them. Tokens only grow, so the first entry is the one parked longest.
+/// Futures are told apart by token rather than by waker, as two futures
+/// polled by the same task carry wakers that wake the same task.
+#[derive(Default)]
+struct Waiters {
+    next_token: u64,
+    parked: BTreeMap<u64, Waker>,
+}
+
+impl Waiters {
+    /// Parks `waker` for a future holding `token`. A future still parked
+    /// keeps its place and only has its waker replaced; otherwise it gets
+    /// a new token at the back.
+    fn park(&mut self, token: &mut Option<u64>, waker: &Waker) {
+        if let Some(parked) = token.and_then(|token| self.parked.get_mut(&token)) {
+            if !parked.will_wake(waker) {
+                *parked = waker.clone();
+            }
+            return;
+        }
+        let new = self.next_token;
+        self.next_token += 1;
+        self.parked.insert(new, waker.clone());
+        *token = Some(new);
+    }
+
+    /// Removes the waker parked under `token`, returning whether it was
+    /// still there rather than taken out to be woken.
+    fn unpark(&mut self, token: u64) -> bool {
+        self.parked.remove(&token).is_some()
+    }
+}
+
+/// For a future dropped before completing, which parked under `token`.
+/// If its waker was taken out to be woken and what it was woken for is
+/// still `available`, the next waiting task gets the wakeup instead.
+fn cancel<T>(
+    mut state: MutexGuard<'_, State<T>>,
+    token: u64,
+    available: impl Fn(&State<T>) -> bool,
+    waiters: impl Fn(&mut State<T>) -> &mut Waiters,
+) {
+    if !waiters(&mut state).unpark(token) && available(&state) {
+        wake_one(state, waiters);
+    }
+}
+
+/// Wakes the task parked longest in `waiters`, once the lock is released.
+fn wake_one<T>(
+    mut state: MutexGuard<'_, State<T>>,
+    waiters: impl Fn(&mut State<T>) -> &mut Waiters,
+) {
+    let woken = waiters(&mut state).parked.pop_first();
+    drop(state);
+    if let Some((_, waker)) = woken {
+        waker.wake();
+    }
+}
+
+/// Takes the wakers out of `waiters` and wakes them once the lock is
+/// released, so the tasks do not immediately block on it.
+fn wake_all<T>(
+    mut state: MutexGuard<'_, State<T>>,
+    waiters: impl Fn(&mut State<T>) -> &mut Waiters,
+) {
+    let woken = mem::take(&mut waiters(&mut state).parked);
+    drop(state);
+    for waker in woken.into_values() {
+        waker.wake();
+    }
+}
+
+impl<T> Sender<T> {
+    /// Sends `message`, waiting while the channel is full.
+    pub fn send(&self, message: T) -> SendFuture<'_, T> {
+        SendFuture {
+            sender: self,
+            message: Some(message),
+            token: None,
+        }
+    }
+
+    pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.shared.lock();
+        if state.receivers == 0 {
+            return Err(TrySendError::Closed(message));
+        }
+        if state.is_full() {
+            return Err(TrySendError::Full(message));
+        }
+        state.messages.enqueue(message);
+        wake_one(state, |state| &mut state.waiting_receivers);
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+/// The last sender closes the channel: waiting receivers wake up to
+/// drain it and then get `None`.
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            wake_all(state, |state| &mut state.waiting_receivers);
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives the oldest message, waiting while the channel is empty.
+    /// Resolves to `None` once it is empty and every `Sender` is gone.
+    pub fn recv(&self) -> RecvFuture<'_, T> {
+        RecvFuture {
+            receiver: self,
+            token: None,
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<T> {
+        let mut state = self.shared.lock();
+        let message = state.messages.dequeue()?;
+        wake_one(state, |state| &mut state.waiting_senders);
+        Some(message)
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().receivers += 1;
+        Receiver {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+/// The last receiver closes the channel: waiting senders wake up and
+/// get their message back.
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            wake_all(state, |state| &mut state.waiting_senders);
+        }
+    }
+}
+
+pub struct SendFuture<'a, T> {
+    sender: &'a Sender<T>,
+    message: Option<T>,
+    /// The token of the waker parked in `waiting_senders`, if any.
+    token: Option<u64>,
+}
+
+/// The message is moved out through `&mut`, never pinned.
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let message = self.message.take().expect("polled after completion");
+        let sent = match self.sender.try_send(message) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Closed(message)) => Err(SendError(message)),
+            Err(TrySendError::Full(message)) => {
+                let sender = self.sender;
+                let mut state = sender.shared.lock();
+                if state.is_full() && state.receivers > 0 {
+                    state.waiting_senders.park(&mut self.token, cx.waker());
+                } else {
+                    // Room was made in between: poll again right away.
+                    cx.waker().wake_by_ref();
+                }
+                drop(state);
+                self.message = Some(message);
+                return Poll::Pending;
+            }
+        };
+        if let Some(token) = self.token.take() {
+            self.sender.shared.lock().waiting_senders.unpark(token);
+        }
+        Poll::Ready(sent)
+    }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            cancel(
+                self.sender.shared.lock(),
+                token,
+                |state| !state.is_full(),
+                |state| &mut state.waiting_senders,
+            );
+        }
+    }
+}
+
+pub struct RecvFuture<'a, T> {
+    receiver: &'a Receiver<T>,
+    /// The token of the waker parked in `waiting_receivers`, if any.
+    token: Option<u64>,
+}
+
+impl<T> Future for RecvFuture<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let receiver = self.receiver;
+        let mut state = receiver.shared.lock();
+        let message = state.messages.dequeue();
+        if message.is_none() && state.senders > 0 {
+            state.waiting_receivers.park(&mut self.token, cx.waker());
+            return Poll::Pending;
+        }
+        if let Some(token) = self.token.take() {
+            state.waiting_receivers.unpark(token);
+        }
+        if message.is_some() {
+            wake_one(state, |state| &mut state.waiting_senders);
+        }
+        Poll::Ready(message)
+    }
+}
+
+impl<T> Drop for RecvFuture<'_, T> {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            cancel(
+                self.receiver.shared.lock(),
+                token,
+                |state| !state.messages.is_empty(),
+                |state| &mut state.waiting_receivers,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+
+    type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+    /// Single-threaded executor that only polls a task after its waker
+    /// was called, so a lost wakeup shows up as a deadlock.
+    fn run_all(tasks: Vec<Task>) {
+        struct TaskWaker {
+            id: usize,
+            ready: Arc<Mutex<VecDeque<usize>>>,
+        }
+
+        impl Wake for TaskWaker {
+            fn wake(self: Arc<Self>) {
+                self.ready.lock().unwrap().push_back(self.id);
+            }
+        }
+
+        let ready = Arc::new(Mutex::new((0..tasks.len()).collect::<VecDeque<_>>()));
+        let mut tasks: Vec<Option<Task>> = tasks.into_iter().map(Some).collect();
+        let mut pending = tasks.len();
+        while pending > 0 {
+            let id = ready
+                .lock()
+                .unwrap()
+                .pop_front()
+                .expect("tasks are pending but none was woken");
+            let waker = Waker::from(Arc::new(TaskWaker {
+                id,
+                ready: Arc::clone(&ready),
+            }));
+            if let Some(task) = tasks[id].as_mut() {
+                if task
+                    .as_mut()
+                    .poll(&mut Context::from_waker(&waker))
+                    .is_ready()
+                {
+                    tasks[id] = None;
+                    pending -= 1;
+                }
+            }
+        }
+    }
+
+    /// Runs a future on the current thread, parking it while pending.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct ThreadWaker(Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn test_bounded_send_waits_for_receiver() {
+        let (tx, rx) = bounded(2);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let producer: Task = Box::pin(async move {
+            for i in 0..100 {
+                tx.send(i).await.unwrap();
+                assert!(tx.shared.lock().messages.len() <= 2);
+            }
+        });
+        let consumer: Task = Box::pin({
+            let received = Arc::clone(&received);
+            async move {
+                while let Some(message) = rx.recv().await {
+                    received.lock().unwrap().push(message);
+                }
+            }
+        });
+        run_all(vec![consumer, producer]);
+        assert_eq!(*received.lock().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    /// Waker that counts how often it was woken.
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn woken(counters: &[Arc<CountingWaker>]) -> Vec<usize> {
+        counters
+            .iter()
+            .map(|counter| counter.0.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    fn poll_with<F: Future + Unpin>(
+        future: &mut F,
+        counter: &Arc<CountingWaker>,
+    ) -> Poll<F::Output> {
+        let waker = Waker::from(Arc::clone(counter));
+        Pin::new(future).poll(&mut Context::from_waker(&waker))
+    }
+
+    #[test]
+    fn test_wakes_one_receiver_per_message() {
+        let (tx, rx) = unbounded();
+        let counters: Vec<_> = (0..3).map(|_| Arc::default()).collect();
+        let mut futures: Vec<_> = counters.iter().map(|_| rx.recv()).collect();
+        for (future, counter) in futures.iter_mut().zip(&counters) {
+            assert!(poll_with(future, counter).is_pending());
+        }
+        tx.try_send(1).unwrap();
+        assert_eq!(woken(&counters), [1, 0, 0]);
+
+        // The woken future is cancelled: its wakeup goes to the next one.
+        drop(futures.remove(0));
+        assert_eq!(woken(&counters), [1, 1, 0]);
+        // A future cancelled while still parked passes nothing on.
+        drop(futures.pop());
+        assert_eq!(woken(&counters), [1, 1, 0]);
+        drop(tx);
+        assert_eq!(woken(&counters), [1, 1, 0]);
+        drop(futures);
+        assert_eq!(rx.try_recv(), Some(1));
+    }
+
+    #[test]
+    fn test_completed_future_leaves_no_stale_waker() {
+        let (tx, rx) = unbounded();
+        let counters: Vec<_> = (0..2).map(|_| Arc::default()).collect();
+        let mut first = rx.recv();
+        let mut second = rx.recv();
+        assert!(poll_with(&mut first, &counters[0]).is_pending());
+        assert!(poll_with(&mut second, &counters[1]).is_pending());
+        tx.try_send(1).unwrap();
+        assert_eq!(woken(&counters), [1, 0]);
+
+        // The second future takes the message the first was woken for.
+        assert_eq!(poll_with(&mut second, &counters[1]), Poll::Ready(Some(1)));
+        assert!(poll_with(&mut first, &counters[0]).is_pending());
+        drop(second);
+        tx.try_send(2).unwrap();
+        assert_eq!(woken(&counters), [2, 0]);
+        assert_eq!(poll_with(&mut first, &counters[0]), Poll::Ready(Some(2)));
+    }
+
+    #[test]
+    fn test_futures_of_one_task_park_apart() {
+        let (tx, rx) = unbounded();
+        let counter = Arc::default();
+        let mut first = rx.recv();
+        let mut second = rx.recv();
+        assert!(poll_with(&mut first, &counter).is_pending());
+        assert!(poll_with(&mut second, &counter).is_pending());
+        // Dropping the first future must leave the second one parked.
+        drop(first);
+        tx.try_send(1).unwrap();
+        assert_eq!(woken(&[Arc::clone(&counter)]), [1]);
+        assert_eq!(poll_with(&mut second, &counter), Poll::Ready(Some(1)));
+    }
+
+    #[test]
+    fn test_closing() {
+        let (tx, rx) = bounded(1);
+        assert_eq!(tx.try_send(1), Ok(()));
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+        drop(tx);
+        assert_eq!(block_on(rx.recv()), Some(1));
+        assert_eq!(block_on(rx.recv()), None);
+
+        let (tx, rx) = unbounded();
+        let other = rx.clone();
+        drop(rx);
+        assert_eq!(block_on(tx.send(1)), Ok(()));
+        assert_eq!(other.try_recv(), Some(1));
+        drop(other);
+        assert_eq!(block_on(tx.send(2)), Err(SendError(2)));
+    }
+
+    #[test]
+    fn test_multiple_producers_and_consumers() {
+        let (tx, rx) = bounded(4);
+        let producers: Vec<_> = (0..4u64)
+            .map(|p| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    block_on(async {
+                        for i in 0..1_000 {
+                            tx.send(p * 1_000 + i).await.unwrap();
+                        }
+                    })
+                })
+            })
+            .collect();
+        drop(tx);
+        let consumers: Vec<_> = (0..3)
+            .map(|_| {
+                let rx = rx.clone();
+                thread::spawn(move || {
+                    block_on(async {
+                        let mut received = Vec::new();
+                        while let Some(message) = rx.recv().await {
+                            received.push(message);
+                        }
+                        received
+                    })
+                })
+            })
+            .collect();
+        drop(rx);
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let mut received: Vec<u64> = consumers
+            .into_iter()
+            .flat_map(|consumer| consumer.join().unwrap())
+            .collect();
+        received.sort_unstable();
+        assert_eq!(received, (0..4_000).collect::<Vec<_>>());
+    }
+}
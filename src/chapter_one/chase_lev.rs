@@ -0,0 +1,352 @@
+use alloc_crate::boxed::Box;
+use alloc_crate::sync::Arc;
+use alloc_crate::vec::Vec;
+use core::cell::{Cell, UnsafeCell};
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{fence, AtomicIsize, AtomicPtr, Ordering};
+
+/// Work-stealing deque (Chase and Lev, 2005), with the memory orderings
+/// of Lê, Pop, Cohen and Zappa Nardelli (2013).
+///
+/// The owning `Worker` pushes and pops at the bottom, like a stack,
+/// while any number of `Stealer`s take elements from the top. Elements
+/// live in the slots `top..bottom` of a circular buffer; only the owner
+/// moves `bottom`, and thieves race for `top` with a compare-and-swap,
+/// which the owner joins when it pops the last element.
+///
+/// When the buffer is full the owner copies the elements into one twice
+/// as large. A thief may still be reading the old buffer, so it is kept
+/// until the deque is dropped.
+pub struct Worker<T> {
+    inner: Arc<Inner<T>>,
+    /// Only one thread may push and pop.
+    marker: PhantomData<Cell<()>>,
+}
+
+/// Handle to steal from a `Worker`, from any thread.
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// Outcome of `Stealer::steal`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Steal<T> {
+    Empty,
+    Success(T),
+    /// Lost a race with another thief or the owner; the deque may still
+    /// hold elements.
+    Retry,
+}
+
+impl<T> Steal<T> {
+    pub fn success(self) -> Option<T> {
+        match self {
+            Steal::Success(element) => Some(element),
+            _ => None,
+        }
+    }
+}
+
+struct Inner<T> {
+    top: AtomicIsize,
+    bottom: AtomicIsize,
+    buffer: AtomicPtr<Buffer<T>>,
+    /// Buffers replaced by larger ones. Only touched by the worker, and
+    /// by `drop`. They stay boxed since thieves may hold pointers to them.
+    #[allow(clippy::vec_box)]
+    retired: UnsafeCell<Vec<Box<Buffer<T>>>>,
+}
+
+/// Slots hold bitwise copies: an element is only owned by whoever wins
+/// it, so slots never drop their contents.
+struct Buffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+const MIN_CAPACITY: usize = 16;
+
+impl<T> Buffer<T> {
+    fn new(capacity: usize) -> Box<Self> {
+        Box::new(Buffer {
+            slots: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+        })
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn slot(&self, index: isize) -> *mut MaybeUninit<T> {
+        self.slots[index as usize & (self.capacity() - 1)].get()
+    }
+
+    /// # Safety
+    ///
+    /// Only the worker may write, and only to slots outside
+    /// `top..bottom`, which no thief reads from.
+    unsafe fn write(&self, index: isize, element: T) {
+        self.slot(index).write(MaybeUninit::new(element))
+    }
+
+    /// Copies the slot without taking ownership; a thief may read it
+    /// while the worker overwrites it after the thief lost its race,
+    /// hence the volatile read and the `MaybeUninit`.
+    ///
+    /// # Safety
+    ///
+    /// The copy may only be assumed initialized by whoever wins the
+    /// element.
+    unsafe fn read(&self, index: isize) -> MaybeUninit<T> {
+        ptr::read_volatile(self.slot(index))
+    }
+}
+
+impl<T> Worker<T> {
+    pub fn new() -> Self {
+        Worker {
+            inner: Arc::new(Inner {
+                top: AtomicIsize::new(0),
+                bottom: AtomicIsize::new(0),
+                buffer: AtomicPtr::new(Box::into_raw(Buffer::new(MIN_CAPACITY))),
+                retired: UnsafeCell::new(Vec::new()),
+            }),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+
+    /// Number of elements, which thieves may be taking concurrently.
+    pub fn len(&self) -> usize {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed);
+        let top = self.inner.top.load(Ordering::Relaxed);
+        (bottom - top).max(0) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&self, element: T) {
+        let inner = &*self.inner;
+        let bottom = inner.bottom.load(Ordering::Relaxed);
+        let top = inner.top.load(Ordering::Acquire);
+        let mut buffer = inner.buffer.load(Ordering::Relaxed);
+        // SAFETY: only the worker replaces the buffer, so it is alive.
+        if bottom - top >= unsafe { (*buffer).capacity() } as isize {
+            buffer = self.grow(top, bottom);
+        }
+        // SAFETY: the slot at `bottom` is outside `top..bottom`.
+        unsafe { (*buffer).write(bottom, element) };
+        fence(Ordering::Release);
+        inner.bottom.store(bottom + 1, Ordering::Relaxed);
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let inner = &*self.inner;
+        let bottom = inner.bottom.load(Ordering::Relaxed) - 1;
+        let buffer = inner.buffer.load(Ordering::Relaxed);
+        // Claim the bottom slot before looking at `top`, so thieves that
+        // have not read `bottom` yet stay away from it.
+        inner.bottom.store(bottom, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        let top = inner.top.load(Ordering::Relaxed);
+        if top > bottom {
+            inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            return None;
+        }
+        // SAFETY: the buffer is alive, and the slot is in `top..=bottom`.
+        let element = unsafe { (*buffer).read(bottom) };
+        if top == bottom {
+            // The last element: race the thieves for it.
+            let won = inner
+                .top
+                .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok();
+            inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            if !won {
+                return None;
+            }
+        }
+        // SAFETY: the element was won, and was initialized by `push`.
+        Some(unsafe { element.assume_init() })
+    }
+
+    /// Moves the elements to a buffer twice as large and retires the
+    /// old one.
+    fn grow(&self, top: isize, bottom: isize) -> *mut Buffer<T> {
+        let inner = &*self.inner;
+        let old = inner.buffer.load(Ordering::Relaxed);
+        // SAFETY: only the worker replaces buffers and touches `retired`.
+        unsafe {
+            let new = Buffer::new((*old).capacity() * 2);
+            for index in top..bottom {
+                new.slot(index).write((*old).read(index));
+            }
+            let new = Box::into_raw(new);
+            inner.buffer.store(new, Ordering::Release);
+            (*inner.retired.get()).push(Box::from_raw(old));
+            new
+        }
+    }
+}
+
+impl<T> Default for Worker<T> {
+    fn default() -> Self {
+        Worker::new()
+    }
+}
+
+impl<T> Stealer<T> {
+    pub fn is_empty(&self) -> bool {
+        let top = self.inner.top.load(Ordering::Acquire);
+        let bottom = self.inner.bottom.load(Ordering::Acquire);
+        bottom <= top
+    }
+
+    /// Takes the element at the top, the one pushed the longest ago.
+    pub fn steal(&self) -> Steal<T> {
+        let inner = &*self.inner;
+        let top = inner.top.load(Ordering::Acquire);
+        fence(Ordering::SeqCst);
+        let bottom = inner.bottom.load(Ordering::Acquire);
+        if top >= bottom {
+            return Steal::Empty;
+        }
+        let buffer = inner.buffer.load(Ordering::Acquire);
+        // SAFETY: buffers are only freed with the deque, and `top` was
+        // in `top..bottom` when read.
+        let element = unsafe { (*buffer).read(top) };
+        if inner
+            .top
+            .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            // The copy belongs to the winner; it is dropped uninitialized.
+            return Steal::Retry;
+        }
+        // SAFETY: the element was won, and was initialized by `push`.
+        Steal::Success(unsafe { element.assume_init() })
+    }
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Stealer {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let top = *self.top.get_mut();
+        let bottom = *self.bottom.get_mut();
+        // SAFETY: with `&mut self` no worker or thief is left, so the
+        // elements in `top..bottom` are ours to drop.
+        unsafe {
+            let buffer = Box::from_raw(*self.buffer.get_mut());
+            for index in top..bottom {
+                (*buffer.slot(index)).assume_init_drop();
+            }
+        }
+    }
+}
+
+// SAFETY: elements are moved between threads but never shared, and the
+// worker side is kept to one thread by `Worker` not being `Sync`.
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::DropCounter;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    const THIEVES: usize = 4;
+    const ELEMENTS: usize = 100_000;
+
+    #[test]
+    fn test_worker_is_lifo_and_thieves_fifo() {
+        let worker = Worker::new();
+        let stealer = worker.stealer();
+        assert_eq!(stealer.steal(), Steal::Empty);
+        assert_eq!(worker.pop(), None);
+        for i in 0..100 {
+            worker.push(i);
+        }
+        assert_eq!(worker.len(), 100);
+        assert_eq!(stealer.steal(), Steal::Success(0));
+        assert_eq!(stealer.steal().success(), Some(1));
+        assert_eq!(worker.pop(), Some(99));
+        assert_eq!(worker.pop(), Some(98));
+        assert!(!stealer.is_empty());
+        while worker.pop().is_some() {}
+        assert!(worker.is_empty());
+        assert_eq!(stealer.steal(), Steal::Empty);
+    }
+
+    /// The worker pushes and pops while thieves steal; every element
+    /// must be taken exactly once.
+    #[test]
+    fn test_concurrent_stealing() {
+        let worker = Worker::new();
+        let done = Arc::new(AtomicUsize::new(0));
+        let thieves: Vec<_> = (0..THIEVES)
+            .map(|_| {
+                let stealer = worker.stealer();
+                let done = Arc::clone(&done);
+                thread::spawn(move || {
+                    let mut stolen = Vec::new();
+                    while done.load(Ordering::Acquire) == 0 || !stealer.is_empty() {
+                        if let Steal::Success(element) = stealer.steal() {
+                            stolen.push(element);
+                        }
+                    }
+                    stolen
+                })
+            })
+            .collect();
+        let mut taken = Vec::new();
+        for i in 0..ELEMENTS {
+            worker.push(i);
+            if i % 3 == 0 {
+                taken.extend(worker.pop());
+            }
+        }
+        done.store(1, Ordering::Release);
+        for thief in thieves {
+            taken.extend(thief.join().unwrap());
+        }
+        taken.extend(std::iter::from_fn(|| worker.pop()));
+        taken.sort_unstable();
+        assert_eq!(taken, (0..ELEMENTS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_drop_releases_elements() {
+        let counter = DropCounter::new();
+        {
+            let worker = Worker::new();
+            let stealer = worker.stealer();
+            for _ in 0..100 {
+                worker.push(counter.token());
+            }
+            stealer.steal();
+            worker.pop();
+            drop(worker);
+            assert_eq!(counter.alive(), 98);
+        }
+        assert_eq!(counter.alive(), 0);
+    }
+}
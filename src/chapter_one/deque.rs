@@ -0,0 +1,598 @@
+use alloc_crate::boxed::Box;
+use alloc_crate::vec::Vec;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ops::{Index, IndexMut};
+use core::ptr::{self, NonNull};
+
+/// Array based double-ended queue.
+///
+/// Like `Queue`, the buffer is used circularly: the elements live in
+/// the `size` slots starting at `head`, wrapping around the end of the
+/// buffer. Only those slots are initialized.
+pub struct ArrayDeque<T> {
+    buff: Vec<MaybeUninit<T>>,
+    head: usize,
+    size: usize,
+}
+
+const MIN_CAPACITY: usize = 8;
+
+impl<T> ArrayDeque<T> {
+    pub fn new() -> Self {
+        ArrayDeque::with_capacity(MIN_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut buff = Vec::new();
+        buff.resize_with(capacity.max(MIN_CAPACITY), MaybeUninit::uninit);
+        ArrayDeque {
+            buff,
+            head: 0,
+            size: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buff.len()
+    }
+
+    pub fn push_back(&mut self, element: T) {
+        self.grow_if_full();
+        let tail = self.slot(self.size);
+        self.buff[tail] = MaybeUninit::new(element);
+        self.size += 1;
+    }
+
+    pub fn push_front(&mut self, element: T) {
+        self.grow_if_full();
+        self.head = self.slot(self.capacity() - 1);
+        self.buff[self.head] = MaybeUninit::new(element);
+        self.size += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        // SAFETY: `head` is the first of the `size` initialized slots,
+        // and it is no longer considered initialized afterwards.
+        let element = unsafe { self.buff[self.head].assume_init_read() };
+        self.head = self.slot(1);
+        self.size -= 1;
+        self.shrink_if_sparse();
+        Some(element)
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let tail = self.slot(self.size - 1);
+        // SAFETY: `tail` is the last of the `size` initialized slots,
+        // and it is no longer considered initialized afterwards.
+        let element = unsafe { self.buff[tail].assume_init_read() };
+        self.size -= 1;
+        self.shrink_if_sparse();
+        Some(element)
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.size.checked_sub(1).and_then(|last| self.get(last))
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.size {
+            return None;
+        }
+        // SAFETY: the first `size` slots from `head` are initialized.
+        Some(unsafe { self.buff[self.slot(index)].assume_init_ref() })
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.size {
+            return None;
+        }
+        let slot = self.slot(index);
+        // SAFETY: the first `size` slots from `head` are initialized.
+        Some(unsafe { self.buff[slot].assume_init_mut() })
+    }
+
+    /// Moves the first `n` elements to the back, in
+    /// O(min(n, len - n)) element moves.
+    ///
+    /// Panics if `n > len`.
+    pub fn rotate_left(&mut self, n: usize) {
+        assert!(n <= self.size, "rotation is larger than the deque");
+        if n <= self.size - n {
+            self.move_front_to_back(n);
+        } else {
+            self.move_back_to_front(self.size - n);
+        }
+    }
+
+    /// Moves the last `n` elements to the front, in
+    /// O(min(n, len - n)) element moves.
+    ///
+    /// Panics if `n > len`.
+    pub fn rotate_right(&mut self, n: usize) {
+        assert!(n <= self.size, "rotation is larger than the deque");
+        if n <= self.size - n {
+            self.move_back_to_front(n);
+        } else {
+            self.move_front_to_back(self.size - n);
+        }
+    }
+
+    /// Returns the elements, front to back, as at most two slices.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let (front, back) = self.ranges();
+        let front = &self.buff[front];
+        let back = &self.buff[back];
+        // SAFETY: both ranges only cover initialized slots, and
+        // `MaybeUninit<T>` has the same layout as `T`.
+        unsafe {
+            (
+                &*(front as *const [MaybeUninit<T>] as *const [T]),
+                &*(back as *const [MaybeUninit<T>] as *const [T]),
+            )
+        }
+    }
+
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        let (front, back) = self.ranges();
+        let (low, high) = self.buff.split_at_mut(front.start);
+        let front = &mut high[..front.end - front.start];
+        let back = &mut low[back];
+        // SAFETY: as in `as_slices`.
+        unsafe {
+            (
+                &mut *(front as *mut [MaybeUninit<T>] as *mut [T]),
+                &mut *(back as *mut [MaybeUninit<T>] as *mut [T]),
+            )
+        }
+    }
+
+    /// Physical position of the `index`-th element.
+    fn slot(&self, index: usize) -> usize {
+        (self.head + index) % self.capacity()
+    }
+
+    /// The initialized slots: from `head` towards the end of the
+    /// buffer, then from the start of the buffer if they wrap around.
+    fn ranges(&self) -> (core::ops::Range<usize>, core::ops::Range<usize>) {
+        let end = self.head + self.size;
+        if end <= self.capacity() {
+            (self.head..end, 0..0)
+        } else {
+            (self.head..self.capacity(), 0..end - self.capacity())
+        }
+    }
+
+    fn move_front_to_back(&mut self, n: usize) {
+        if self.size == self.capacity() {
+            self.head = self.slot(n);
+            return;
+        }
+        for _ in 0..n {
+            let tail = self.slot(self.size);
+            self.buff[tail] = mem::replace(&mut self.buff[self.head], MaybeUninit::uninit());
+            self.head = self.slot(1);
+        }
+    }
+
+    fn move_back_to_front(&mut self, n: usize) {
+        if self.size == self.capacity() {
+            self.head = self.slot(self.capacity() - n);
+            return;
+        }
+        for _ in 0..n {
+            let tail = self.slot(self.size - 1);
+            self.head = self.slot(self.capacity() - 1);
+            self.buff[self.head] = mem::replace(&mut self.buff[tail], MaybeUninit::uninit());
+        }
+    }
+
+    fn grow_if_full(&mut self) {
+        if self.size == self.capacity() {
+            self.resize(self.capacity() * 2);
+        }
+    }
+
+    fn shrink_if_sparse(&mut self) {
+        if self.capacity() > MIN_CAPACITY && self.size <= self.capacity() / 4 {
+            self.resize(self.capacity() / 2);
+        }
+    }
+
+    /// Moves the elements, front first, to the start of a new buffer.
+    fn resize(&mut self, capacity: usize) {
+        let capacity = capacity.max(MIN_CAPACITY);
+        let mut buff = Vec::with_capacity(capacity);
+        for i in 0..self.size {
+            let slot = self.slot(i);
+            buff.push(mem::replace(&mut self.buff[slot], MaybeUninit::uninit()));
+        }
+        buff.resize_with(capacity, MaybeUninit::uninit);
+        self.buff = buff;
+        self.head = 0;
+    }
+}
+
+impl<T> Default for ArrayDeque<T> {
+    fn default() -> Self {
+        ArrayDeque::new()
+    }
+}
+
+impl<T> Index<usize> for ArrayDeque<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T> IndexMut<usize> for ArrayDeque<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("index out of bounds")
+    }
+}
+
+impl<T> Drop for ArrayDeque<T> {
+    fn drop(&mut self) {
+        let (front, back) = self.as_mut_slices();
+        // SAFETY: the slices cover exactly the initialized slots, which
+        // are never read again.
+        unsafe {
+            ptr::drop_in_place(front);
+            ptr::drop_in_place(back);
+        }
+    }
+}
+
+/// Doubly linked double-ended queue.
+///
+/// Like `LinkedQueue`, the deque owns its nodes through raw pointers
+/// to leaked boxes, since every node is pointed to by both neighbours.
+pub struct LinkedDeque<T> {
+    head: Link<T>,
+    tail: Link<T>,
+    size: usize,
+    marker: PhantomData<Box<Node<T>>>,
+}
+
+type Link<T> = Option<NonNull<Node<T>>>;
+
+struct Node<T> {
+    element: T,
+    prev: Link<T>,
+    next: Link<T>,
+}
+
+impl<T> LinkedDeque<T> {
+    pub fn new() -> Self {
+        LinkedDeque {
+            head: None,
+            tail: None,
+            size: 0,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn push_front(&mut self, element: T) {
+        let node = Box::new(Node {
+            element,
+            prev: None,
+            next: None,
+        });
+        self.link_front(NonNull::from(Box::leak(node)));
+    }
+
+    pub fn push_back(&mut self, element: T) {
+        let node = Box::new(Node {
+            element,
+            prev: None,
+            next: None,
+        });
+        self.link_back(NonNull::from(Box::leak(node)));
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.unlink_front().map(|node| {
+            // SAFETY: the node was leaked from a `Box` and is now unlinked.
+            unsafe { Box::from_raw(node.as_ptr()) }.element
+        })
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.unlink_back().map(|node| {
+            // SAFETY: the node was leaked from a `Box` and is now unlinked.
+            unsafe { Box::from_raw(node.as_ptr()) }.element
+        })
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        // SAFETY: the node is owned by `self`, which outlives the reference.
+        self.head.map(|node| unsafe { &(*node.as_ptr()).element })
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        // SAFETY: the node is owned by `self`, which outlives the reference.
+        self.tail.map(|node| unsafe { &(*node.as_ptr()).element })
+    }
+
+    /// Walks from whichever end is closer to `index`.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        // SAFETY: the node is owned by `self`, which outlives the reference.
+        self.node(index)
+            .map(|node| unsafe { &(*node.as_ptr()).element })
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        // SAFETY: as in `get`, with `self` borrowed mutably.
+        self.node(index)
+            .map(|node| unsafe { &mut (*node.as_ptr()).element })
+    }
+
+    /// Moves the first `n` elements to the back by relinking
+    /// min(n, len - n) nodes.
+    ///
+    /// Panics if `n > len`.
+    pub fn rotate_left(&mut self, n: usize) {
+        assert!(n <= self.size, "rotation is larger than the deque");
+        if n <= self.size - n {
+            for _ in 0..n {
+                let node = self.unlink_front().unwrap();
+                self.link_back(node);
+            }
+        } else {
+            self.rotate_right(self.size - n);
+        }
+    }
+
+    /// Moves the last `n` elements to the front by relinking
+    /// min(n, len - n) nodes.
+    ///
+    /// Panics if `n > len`.
+    pub fn rotate_right(&mut self, n: usize) {
+        assert!(n <= self.size, "rotation is larger than the deque");
+        if n <= self.size - n {
+            for _ in 0..n {
+                let node = self.unlink_back().unwrap();
+                self.link_front(node);
+            }
+        } else {
+            self.rotate_left(self.size - n);
+        }
+    }
+
+    fn node(&self, index: usize) -> Link<T> {
+        if index >= self.size {
+            return None;
+        }
+        // SAFETY: every link followed here points to a node owned by `self`.
+        unsafe {
+            if index < self.size / 2 {
+                let mut node = self.head?;
+                for _ in 0..index {
+                    node = (*node.as_ptr()).next?;
+                }
+                Some(node)
+            } else {
+                let mut node = self.tail?;
+                for _ in index + 1..self.size {
+                    node = (*node.as_ptr()).prev?;
+                }
+                Some(node)
+            }
+        }
+    }
+
+    fn link_front(&mut self, node: NonNull<Node<T>>) {
+        // SAFETY: `node` is not linked yet and `head` is owned by `self`.
+        unsafe {
+            (*node.as_ptr()).prev = None;
+            (*node.as_ptr()).next = self.head;
+            match self.head {
+                Some(head) => (*head.as_ptr()).prev = Some(node),
+                None => self.tail = Some(node),
+            }
+        }
+        self.head = Some(node);
+        self.size += 1;
+    }
+
+    fn link_back(&mut self, node: NonNull<Node<T>>) {
+        // SAFETY: `node` is not linked yet and `tail` is owned by `self`.
+        unsafe {
+            (*node.as_ptr()).next = None;
+            (*node.as_ptr()).prev = self.tail;
+            match self.tail {
+                Some(tail) => (*tail.as_ptr()).next = Some(node),
+                None => self.head = Some(node),
+            }
+        }
+        self.tail = Some(node);
+        self.size += 1;
+    }
+
+    fn unlink_front(&mut self) -> Link<T> {
+        self.head.inspect(|node| {
+            // SAFETY: `node` and its successor are owned by `self`.
+            unsafe {
+                self.head = (*node.as_ptr()).next;
+                match self.head {
+                    Some(head) => (*head.as_ptr()).prev = None,
+                    None => self.tail = None,
+                }
+            }
+            self.size -= 1;
+        })
+    }
+
+    fn unlink_back(&mut self) -> Link<T> {
+        self.tail.inspect(|node| {
+            // SAFETY: `node` and its predecessor are owned by `self`.
+            unsafe {
+                self.tail = (*node.as_ptr()).prev;
+                match self.tail {
+                    Some(tail) => (*tail.as_ptr()).next = None,
+                    None => self.head = None,
+                }
+            }
+            self.size -= 1;
+        })
+    }
+}
+
+impl<T> Default for LinkedDeque<T> {
+    fn default() -> Self {
+        LinkedDeque::new()
+    }
+}
+
+impl<T> Index<usize> for LinkedDeque<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T> IndexMut<usize> for LinkedDeque<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("index out of bounds")
+    }
+}
+
+impl<T> Drop for LinkedDeque<T> {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
+
+// SAFETY: both deques own their elements like a `Vec` or a `Box` chain.
+unsafe impl<T: Send> Send for ArrayDeque<T> {}
+unsafe impl<T: Sync> Sync for ArrayDeque<T> {}
+unsafe impl<T: Send> Send for LinkedDeque<T> {}
+unsafe impl<T: Sync> Sync for LinkedDeque<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_array_push_and_pop_both_ends() {
+        let mut d: ArrayDeque<i32> = ArrayDeque::new();
+        assert_eq!(d.pop_front(), None);
+        assert_eq!(d.pop_back(), None);
+        for i in 0..100 {
+            d.push_back(i);
+            d.push_front(-i);
+        }
+        assert_eq!(d.len(), 200);
+        assert_eq!(d.front(), Some(&-99));
+        assert_eq!(d.back(), Some(&99));
+        assert_eq!(d[100], 0);
+        d[100] = 1000;
+        assert_eq!(d.get(100), Some(&1000));
+        assert_eq!(d.get(200), None);
+        for i in (1..100).rev() {
+            assert_eq!(d.pop_front(), Some(-i));
+            assert_eq!(d.pop_back(), Some(i));
+        }
+        assert_eq!(d.pop_back(), Some(1000));
+        assert_eq!(d.pop_front(), Some(0));
+        assert!(d.is_empty());
+        assert_eq!(d.capacity(), MIN_CAPACITY);
+    }
+
+    #[test]
+    fn test_array_as_slices_and_rotate() {
+        let mut d: ArrayDeque<String> = ArrayDeque::new();
+        for i in 0..4 {
+            d.push_back(i.to_string());
+        }
+        d.push_front("-1".to_string());
+        let (front, back) = d.as_slices();
+        assert_eq!(front, ["-1"]);
+        assert_eq!(back, ["0", "1", "2", "3"]);
+        d.rotate_left(2);
+        assert_eq!(
+            [d.as_slices().0, d.as_slices().1].concat(),
+            ["1", "2", "3", "-1", "0"]
+        );
+        d.rotate_right(4);
+        assert_eq!(
+            [d.as_slices().0, d.as_slices().1].concat(),
+            ["2", "3", "-1", "0", "1"]
+        );
+        for i in 5..8 {
+            d.push_back(i.to_string());
+        }
+        assert_eq!(d.len(), d.capacity());
+        d.rotate_left(3);
+        d.as_mut_slices().0[0].push('!');
+        assert_eq!(
+            [d.as_slices().0, d.as_slices().1].concat(),
+            ["0!", "1", "5", "6", "7", "2", "3", "-1"]
+        );
+    }
+
+    #[test]
+    fn test_linked_push_pop_and_index() {
+        let mut d: LinkedDeque<i32> = LinkedDeque::new();
+        assert_eq!(d.pop_front(), None);
+        for i in 0..10 {
+            d.push_back(i);
+        }
+        d.push_front(-1);
+        assert_eq!(d.len(), 11);
+        assert_eq!(d[0], -1);
+        assert_eq!(d[10], 9);
+        d[3] = 30;
+        assert_eq!(d.get(3), Some(&30));
+        assert_eq!(d.get(11), None);
+        assert_eq!(d.pop_back(), Some(9));
+        assert_eq!(d.pop_front(), Some(-1));
+        assert_eq!(d.front(), Some(&0));
+        assert_eq!(d.back(), Some(&8));
+    }
+
+    #[test]
+    fn test_linked_rotate() {
+        let mut d: LinkedDeque<i32> = LinkedDeque::new();
+        for i in 0..5 {
+            d.push_back(i);
+        }
+        d.rotate_left(1);
+        d.rotate_right(4);
+        d.rotate_left(5);
+        let mut elements = Vec::new();
+        while let Some(element) = d.pop_front() {
+            elements.push(element);
+        }
+        assert_eq!(elements, [2, 3, 4, 0, 1]);
+    }
+}
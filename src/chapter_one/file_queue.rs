@@ -0,0 +1,526 @@
+use super::queue::Queue;
+use std::convert::{TryFrom, TryInto};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Length and checksum, both little-endian `u32`, before each record.
+/// The checksum covers the length as well as the payload.
+const HEADER: u64 = 8;
+const SEGMENT_EXTENSION: &str = "seg";
+const OFFSET_FILE: &str = "consumer.offset";
+
+/// When a `FileQueue` forces its writes to disk. Whatever was not
+/// synced when the machine crashes may be lost, and consumed records
+/// may be delivered again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// After every enqueue and every dequeue.
+    Always,
+    /// After every `n` enqueues and after every `n` dequeues, each
+    /// counted separately.
+    Every(usize),
+    /// Only when a segment is sealed or `sync` is called.
+    Never,
+}
+
+impl SyncPolicy {
+    /// Counts one more unsynced operation and tells whether they must be
+    /// synced now, starting the count over if so.
+    fn due(self, unsynced: &mut usize) -> bool {
+        *unsynced += 1;
+        let due = match self {
+            SyncPolicy::Always => true,
+            SyncPolicy::Every(n) => *unsynced >= n,
+            SyncPolicy::Never => false,
+        };
+        if due {
+            *unsynced = 0;
+        }
+        due
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FileQueueOptions {
+    /// Size past which a segment is sealed and a new one started. A
+    /// segment always holds at least one record, however large.
+    pub segment_size: u64,
+    pub sync: SyncPolicy,
+}
+
+impl Default for FileQueueOptions {
+    fn default() -> Self {
+        FileQueueOptions {
+            segment_size: 16 << 20,
+            sync: SyncPolicy::Always,
+        }
+    }
+}
+
+/// Queue of byte records persisted in a directory.
+///
+/// Records are appended to numbered segment files as frames of
+/// `HEADER` followed by the payload. The consumer position, a segment
+/// and an offset into it, is written to `OFFSET_FILE` after every
+/// dequeue by replacing the file, and a segment is deleted once the
+/// consumer has moved past it.
+///
+/// Opening the directory again resumes from the saved position. Every
+/// segment is checked frame by frame, and a segment is truncated at the
+/// first frame that is incomplete or fails its checksum, which is what
+/// a write torn by a crash looks like.
+pub struct FileQueue {
+    dir: PathBuf,
+    options: FileQueueOptions,
+    /// Ids of the segments on disk, oldest first: the consumer reads the
+    /// front one and the producer appends to the back one.
+    segments: Queue<u64>,
+    last_segment: u64,
+    reader: File,
+    read_offset: u64,
+    writer: File,
+    write_offset: u64,
+    len: usize,
+    /// Enqueues whose records are not synced yet.
+    unsynced_writes: usize,
+    /// Dequeues whose position is not synced yet.
+    unsynced_reads: usize,
+}
+
+impl FileQueue {
+    /// Opens the queue stored in `dir` with the default options,
+    /// creating it if needed.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        FileQueue::open_with(dir, FileQueueOptions::default())
+    }
+
+    pub fn open_with<P: AsRef<Path>>(dir: P, options: FileQueueOptions) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            if let Some(id) = path.file_stem().and_then(|s| s.to_str()?.parse().ok()) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+
+        let saved = read_position(&dir.join(OFFSET_FILE))?;
+        let (first, mut read_offset) = saved.unwrap_or((ids.first().copied().unwrap_or(0), 0));
+        // Segments the consumer had moved past before it could delete them.
+        for &id in ids.iter().filter(|&&id| id < first) {
+            fs::remove_file(segment_path(&dir, id))?;
+        }
+        ids.retain(|&id| id >= first);
+        if ids.first() != Some(&first) {
+            read_offset = 0;
+        }
+        if ids.is_empty() {
+            File::create(segment_path(&dir, first))?;
+            ids.push(first);
+        }
+
+        let mut len = 0;
+        let mut write_offset = 0;
+        for (i, &id) in ids.iter().enumerate() {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(segment_path(&dir, id))?;
+            let end = file.metadata()?.len();
+            let mut offset = if i == 0 { read_offset.min(end) } else { 0 };
+            if i == 0 {
+                read_offset = offset;
+            }
+            while let Some(record) = read_frame(&mut file, offset, end)? {
+                offset += HEADER + record.len() as u64;
+                len += 1;
+            }
+            if offset < end {
+                file.set_len(offset)?;
+                file.sync_data()?;
+            }
+            write_offset = offset;
+        }
+
+        let last_segment = *ids.last().unwrap();
+        Ok(FileQueue {
+            reader: File::open(segment_path(&dir, ids[0]))?,
+            writer: OpenOptions::new()
+                .append(true)
+                .open(segment_path(&dir, last_segment))?,
+            segments: ids.into_iter().collect(),
+            dir,
+            options,
+            last_segment,
+            read_offset,
+            write_offset,
+            len,
+            unsynced_writes: 0,
+            unsynced_reads: 0,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn enqueue(&mut self, record: &[u8]) -> io::Result<()> {
+        let payload_len = u32::try_from(record.len())
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "record is too large"))?;
+        let frame_len = HEADER + u64::from(payload_len);
+        if self.write_offset > 0 && self.write_offset + frame_len > self.options.segment_size {
+            self.rotate()?;
+        }
+        let mut frame = Vec::with_capacity(frame_len as usize);
+        frame.extend_from_slice(&payload_len.to_le_bytes());
+        frame.extend_from_slice(&frame_checksum(payload_len, record).to_le_bytes());
+        frame.extend_from_slice(record);
+        if let Err(err) = self.writer.write_all(&frame) {
+            // Do not leave a torn frame for later records to follow.
+            let _ = self.writer.set_len(self.write_offset);
+            return Err(err);
+        }
+        self.write_offset += frame_len;
+        self.len += 1;
+        if self.options.sync.due(&mut self.unsynced_writes) {
+            self.writer.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Removes the oldest record and saves the new consumer position.
+    pub fn dequeue(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let segment = *self.segments.peek().expect("there is always a segment");
+            let end = if segment == self.last_segment {
+                self.write_offset
+            } else {
+                self.reader.metadata()?.len()
+            };
+            if self.read_offset < end {
+                let record =
+                    read_frame(&mut self.reader, self.read_offset, end)?.ok_or_else(|| {
+                        io::Error::new(ErrorKind::InvalidData, "corrupt record in segment")
+                    })?;
+                self.read_offset += HEADER + record.len() as u64;
+                self.len -= 1;
+                self.save_position()?;
+                return Ok(Some(record));
+            }
+            if segment == self.last_segment {
+                return Ok(None);
+            }
+            self.segments.dequeue();
+            let next = *self.segments.peek().expect("the last segment is kept");
+            self.reader = File::open(segment_path(&self.dir, next))?;
+            self.read_offset = 0;
+            self.save_position()?;
+            fs::remove_file(segment_path(&self.dir, segment))?;
+        }
+    }
+
+    /// Forces the records and the consumer position to disk.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.unsynced_writes = 0;
+        self.unsynced_reads = 0;
+        self.writer.sync_data()?;
+        self.write_position(true)
+    }
+
+    /// Seals the last segment and starts a new one. The directory is
+    /// synced too, or the new segment could vanish in a crash along with
+    /// the records written to it.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.sync_data()?;
+        let id = self.last_segment + 1;
+        self.writer = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(segment_path(&self.dir, id))?;
+        self.segments.enqueue(id);
+        self.last_segment = id;
+        self.write_offset = 0;
+        self.sync_dir()
+    }
+
+    fn save_position(&mut self) -> io::Result<()> {
+        let sync = self.options.sync.due(&mut self.unsynced_reads);
+        self.write_position(sync)
+    }
+
+    /// Replaces `OFFSET_FILE` through a rename, so that a crash leaves
+    /// either the old position or the new one. Syncing covers the rename
+    /// as well, which lives in the directory.
+    fn write_position(&self, sync: bool) -> io::Result<()> {
+        let segment = *self.segments.peek().expect("there is always a segment");
+        let mut contents = Vec::with_capacity(20);
+        contents.extend_from_slice(&segment.to_le_bytes());
+        contents.extend_from_slice(&self.read_offset.to_le_bytes());
+        contents.extend_from_slice(&crc32(&contents).to_le_bytes());
+        let tmp = self.dir.join(format!("{}.tmp", OFFSET_FILE));
+        let mut file = File::create(&tmp)?;
+        file.write_all(&contents)?;
+        if sync {
+            file.sync_data()?;
+        }
+        fs::rename(tmp, self.dir.join(OFFSET_FILE))?;
+        if sync {
+            self.sync_dir()?;
+        }
+        Ok(())
+    }
+
+    fn sync_dir(&self) -> io::Result<()> {
+        File::open(&self.dir)?.sync_all()
+    }
+}
+
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", id, SEGMENT_EXTENSION))
+}
+
+/// The saved consumer position, or `None` if there is none or it is
+/// damaged, in which case consumption restarts from the oldest segment.
+fn read_position(path: &Path) -> io::Result<Option<(u64, u64)>> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    if contents.len() != 20 || crc32(&contents[..16]).to_le_bytes() != contents[16..] {
+        return Ok(None);
+    }
+    let word = |i: usize| u64::from_le_bytes(contents[i..i + 8].try_into().unwrap());
+    Ok(Some((word(0), word(8))))
+}
+
+/// Reads the frame at `offset`, or returns `None` if it does not fit
+/// before `end` or fails its checksum.
+fn read_frame(file: &mut File, offset: u64, end: u64) -> io::Result<Option<Vec<u8>>> {
+    if offset + HEADER > end {
+        return Ok(None);
+    }
+    let mut header = [0; HEADER as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut header)?;
+    let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let checksum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if offset + HEADER + u64::from(len) > end {
+        return Ok(None);
+    }
+    let mut record = vec![0; len as usize];
+    file.read_exact(&mut record)?;
+    Ok(Some(record).filter(|record| frame_checksum(len, record) == checksum))
+}
+
+/// CRC-32 of the length field followed by the payload. Covering the
+/// length means no frame is checksummed over empty input, whose CRC-32
+/// is zero, so the zeros a torn write often leaves behind never pass
+/// for an empty record.
+fn frame_checksum(len: u32, record: &[u8]) -> u32 {
+    crc32(len.to_le_bytes().iter().chain(record))
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-32 (IEEE 802.3).
+fn crc32<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u32 {
+    !bytes.into_iter().fold(!0, |crc, &byte| {
+        CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A fresh directory under the system temp dir, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "ads-file-queue-{}-{}",
+                std::process::id(),
+                name
+            ));
+            let _ = fs::remove_dir_all(&path);
+            TempDir(path)
+        }
+
+        fn segments(&self) -> Vec<PathBuf> {
+            let mut segments: Vec<PathBuf> = fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.extension().unwrap() == SEGMENT_EXTENSION)
+                .collect();
+            segments.sort();
+            segments
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn small_segments() -> FileQueueOptions {
+        FileQueueOptions {
+            segment_size: 64,
+            sync: SyncPolicy::Never,
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+        assert_ne!(frame_checksum(0, b""), 0);
+    }
+
+    #[test]
+    fn test_records_and_position_survive_reopening() {
+        let dir = TempDir::new("reopen");
+        {
+            let mut q = FileQueue::open(&dir.0).unwrap();
+            assert_eq!(q.dequeue().unwrap(), None);
+            for record in ["a", "bb", "ccc"] {
+                q.enqueue(record.as_bytes()).unwrap();
+            }
+            assert_eq!(q.dequeue().unwrap(), Some(b"a".to_vec()));
+        }
+        let mut q = FileQueue::open(&dir.0).unwrap();
+        assert_eq!(q.len(), 2);
+        q.enqueue(b"").unwrap();
+        assert_eq!(q.dequeue().unwrap(), Some(b"bb".to_vec()));
+        assert_eq!(q.dequeue().unwrap(), Some(b"ccc".to_vec()));
+        assert_eq!(q.dequeue().unwrap(), Some(Vec::new()));
+        assert_eq!(q.dequeue().unwrap(), None);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_rotates_and_deletes_consumed_segments() {
+        let dir = TempDir::new("rotate");
+        let mut q = FileQueue::open_with(&dir.0, small_segments()).unwrap();
+        for i in 0..20u32 {
+            q.enqueue(&[i as u8; 24]).unwrap();
+        }
+        assert_eq!(dir.segments().len(), 10);
+        for i in 0..15u32 {
+            assert_eq!(q.dequeue().unwrap(), Some(vec![i as u8; 24]));
+        }
+        assert_eq!(dir.segments().len(), 3);
+        drop(q);
+        let mut q = FileQueue::open_with(&dir.0, small_segments()).unwrap();
+        assert_eq!(q.len(), 5);
+        for i in 15..20u32 {
+            assert_eq!(q.dequeue().unwrap(), Some(vec![i as u8; 24]));
+        }
+        assert_eq!(q.dequeue().unwrap(), None);
+        assert_eq!(dir.segments().len(), 1);
+    }
+
+    #[test]
+    fn test_dequeues_do_not_use_up_enqueue_syncs() {
+        let dir = TempDir::new("sync");
+        let options = FileQueueOptions {
+            sync: SyncPolicy::Every(2),
+            ..small_segments()
+        };
+        let mut q = FileQueue::open_with(&dir.0, options).unwrap();
+        q.enqueue(b"a").unwrap();
+        q.dequeue().unwrap();
+        assert_eq!((q.unsynced_writes, q.unsynced_reads), (1, 1));
+        q.enqueue(b"b").unwrap();
+        assert_eq!((q.unsynced_writes, q.unsynced_reads), (0, 1));
+        q.dequeue().unwrap();
+        assert_eq!((q.unsynced_writes, q.unsynced_reads), (0, 0));
+        q.enqueue(b"c").unwrap();
+        q.sync().unwrap();
+        assert_eq!((q.unsynced_writes, q.unsynced_reads), (0, 0));
+    }
+
+    #[test]
+    fn test_recovers_from_torn_and_corrupt_frames() {
+        let dir = TempDir::new("recover");
+        {
+            let mut q = FileQueue::open(&dir.0).unwrap();
+            for record in ["first", "second", "third"] {
+                q.enqueue(record.as_bytes()).unwrap();
+            }
+        }
+        let segment = dir.segments().pop().unwrap();
+        // A torn write: a header announcing more bytes than were written.
+        let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+        file.write_all(&[100, 0, 0, 0, 1, 2, 3, 4, b'x']).unwrap();
+        drop(file);
+        let mut q = FileQueue::open(&dir.0).unwrap();
+        assert_eq!(q.len(), 3);
+        q.enqueue(b"fourth").unwrap();
+        drop(q);
+
+        // Flip the last byte of "third": it fails its checksum, and the
+        // frames after it are cut off as well.
+        let mut contents = fs::read(&segment).unwrap();
+        let third = contents.len() - (HEADER as usize + 6) - 1;
+        contents[third] ^= 0xff;
+        fs::write(&segment, contents).unwrap();
+        let mut q = FileQueue::open(&dir.0).unwrap();
+        assert_eq!(q.len(), 2);
+        assert_eq!(q.dequeue().unwrap(), Some(b"first".to_vec()));
+        assert_eq!(q.dequeue().unwrap(), Some(b"second".to_vec()));
+        assert_eq!(q.dequeue().unwrap(), None);
+    }
+
+    #[test]
+    fn test_zero_filled_tail_is_not_read_as_records() {
+        let dir = TempDir::new("zeros");
+        {
+            let mut q = FileQueue::open(&dir.0).unwrap();
+            q.enqueue(b"only").unwrap();
+        }
+        let segment = dir.segments().pop().unwrap();
+        let end = fs::metadata(&segment).unwrap().len();
+        // A crash after the file grew but before its data was written.
+        let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+        file.write_all(&[0; 64]).unwrap();
+        drop(file);
+        let mut q = FileQueue::open(&dir.0).unwrap();
+        assert_eq!(q.len(), 1);
+        assert_eq!(fs::metadata(&segment).unwrap().len(), end);
+        assert_eq!(q.dequeue().unwrap(), Some(b"only".to_vec()));
+        assert_eq!(q.dequeue().unwrap(), None);
+    }
+}
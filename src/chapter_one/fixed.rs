@@ -0,0 +1,237 @@
+use core::mem::MaybeUninit;
+use core::ptr;
+
+/// Stack of at most `N` elements stored inline: it never allocates, and
+/// `push` hands the element back once the stack is full.
+///
+/// Only the first `len` slots are initialized.
+pub struct ArrayStack<T, const N: usize> {
+    buff: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> ArrayStack<T, N> {
+    pub const fn new() -> Self {
+        ArrayStack {
+            buff: [const { MaybeUninit::uninit() }; N],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn push(&mut self, element: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(element);
+        }
+        self.buff[self.len] = MaybeUninit::new(element);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.len -= 1;
+        // SAFETY: the slot was the last initialized one, and it is no
+        // longer considered initialized afterwards.
+        Some(unsafe { self.buff[self.len].assume_init_read() })
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.as_slice().last()
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.as_mut_slice().last_mut()
+    }
+
+    /// Returns the elements from the bottom of the stack to the top.
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the first `len` slots are initialized.
+        unsafe { &*(&self.buff[..self.len] as *const [MaybeUninit<T>] as *const [T]) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: the first `len` slots are initialized.
+        unsafe { &mut *(&mut self.buff[..self.len] as *mut [MaybeUninit<T>] as *mut [T]) }
+    }
+}
+
+impl<T, const N: usize> Default for ArrayStack<T, N> {
+    fn default() -> Self {
+        ArrayStack::new()
+    }
+}
+
+impl<T: core::fmt::Debug, const N: usize> core::fmt::Debug for ArrayStack<T, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayStack<T, N> {
+    fn drop(&mut self) {
+        // SAFETY: the slice covers exactly the initialized slots, which
+        // are never read again.
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) }
+    }
+}
+
+/// Queue of at most `N` elements stored inline in a circular buffer: it
+/// never allocates, and `enqueue` hands the element back once the queue
+/// is full.
+///
+/// Only the `size` slots starting at `head`, wrapping around, are
+/// initialized.
+pub struct ArrayQueue<T, const N: usize> {
+    buff: [MaybeUninit<T>; N],
+    head: usize,
+    size: usize,
+}
+
+impl<T, const N: usize> ArrayQueue<T, N> {
+    pub const fn new() -> Self {
+        ArrayQueue {
+            buff: [const { MaybeUninit::uninit() }; N],
+            head: 0,
+            size: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.size == N
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn enqueue(&mut self, element: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(element);
+        }
+        self.buff[(self.head + self.size) % N] = MaybeUninit::new(element);
+        self.size += 1;
+        Ok(())
+    }
+
+    pub fn dequeue(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        // SAFETY: `head` is the first initialized slot, and it is no
+        // longer considered initialized afterwards.
+        let element = unsafe { self.buff[self.head].assume_init_read() };
+        self.head = (self.head + 1) % N;
+        self.size -= 1;
+        Some(element)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        // SAFETY: `head` is the first initialized slot.
+        Some(unsafe { self.buff[self.head].assume_init_ref() })
+    }
+}
+
+impl<T, const N: usize> Default for ArrayQueue<T, N> {
+    fn default() -> Self {
+        ArrayQueue::new()
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayQueue<T, N> {
+    fn drop(&mut self) {
+        while self.dequeue().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::DropCounter;
+
+    #[test]
+    fn test_stack_rejects_when_full() {
+        let mut s: ArrayStack<i32, 3> = ArrayStack::new();
+        assert_eq!(s.pop(), None);
+        for i in 0..3 {
+            assert_eq!(s.push(i), Ok(()));
+        }
+        assert!(s.is_full());
+        assert_eq!(s.push(3), Err(3));
+        assert_eq!(s.peek(), Some(&2));
+        if let Some(top) = s.peek_mut() {
+            *top = 20;
+        }
+        assert_eq!(format!("{:?}", s), "[0, 1, 20]");
+        assert_eq!(s.pop(), Some(20));
+        assert_eq!(s.push(3), Ok(()));
+        assert_eq!(s.as_slice(), [0, 1, 3]);
+    }
+
+    #[test]
+    fn test_queue_wraps_around() {
+        let mut q: ArrayQueue<i32, 4> = ArrayQueue::new();
+        assert_eq!(q.dequeue(), None);
+        for round in 0..10 {
+            for i in 0..4 {
+                assert_eq!(q.enqueue(round * 4 + i), Ok(()));
+            }
+            assert_eq!(q.enqueue(-1), Err(-1));
+            assert_eq!(q.peek(), Some(&(round * 4)));
+            for i in 0..3 {
+                assert_eq!(q.dequeue(), Some(round * 4 + i));
+            }
+            assert_eq!(q.dequeue(), Some(round * 4 + 3));
+            assert!(q.is_empty());
+            q.enqueue(0).unwrap();
+            q.dequeue();
+        }
+        let mut empty: ArrayQueue<i32, 0> = ArrayQueue::new();
+        assert_eq!(empty.enqueue(1), Err(1));
+    }
+
+    #[test]
+    fn test_drop_releases_elements() {
+        let counter = DropCounter::new();
+        {
+            let mut s: ArrayStack<_, 8> = ArrayStack::new();
+            let mut q: ArrayQueue<_, 8> = ArrayQueue::new();
+            for _ in 0..5 {
+                s.push(counter.token()).unwrap();
+                q.enqueue(counter.token()).unwrap();
+            }
+            s.pop();
+            q.dequeue();
+            assert_eq!(counter.alive(), 8);
+        }
+        assert_eq!(counter.alive(), 0);
+    }
+}
@@ -1,2 +1,18 @@
+pub mod atomic_queue;
+pub mod atomic_stack;
+pub mod bounded_queue;
+#[cfg(feature = "std")]
+pub mod channel;
+pub mod chase_lev;
+pub mod deque;
+#[cfg(feature = "std")]
+pub mod file_queue;
+pub mod fixed;
+pub mod monoid;
+pub mod multi_stack;
+pub mod persistent_queue;
+pub mod persistent_stack;
 pub mod queue;
+pub mod shadow_queue;
+pub mod shadow_stack;
 pub mod stack;
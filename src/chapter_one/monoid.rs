@@ -0,0 +1,260 @@
+use super::stack::Stack;
+use core::marker::PhantomData;
+use core::ops::Add;
+
+/// Associative operation a `MonoidStack` or `MonoidQueue` aggregates
+/// its elements with. The identity is left implicit: the aggregate of
+/// an empty structure is `None`.
+pub trait Monoid<T> {
+    fn combine(a: &T, b: &T) -> T;
+}
+
+pub struct Min;
+
+pub struct Max;
+
+pub struct Sum;
+
+/// Greatest common divisor of unsigned integers.
+pub struct Gcd;
+
+impl<T: Ord + Clone> Monoid<T> for Min {
+    fn combine(a: &T, b: &T) -> T {
+        core::cmp::min(a, b).clone()
+    }
+}
+
+impl<T: Ord + Clone> Monoid<T> for Max {
+    fn combine(a: &T, b: &T) -> T {
+        core::cmp::max(a, b).clone()
+    }
+}
+
+impl<T: Add<Output = T> + Clone> Monoid<T> for Sum {
+    fn combine(a: &T, b: &T) -> T {
+        a.clone() + b.clone()
+    }
+}
+
+macro_rules! impl_gcd {
+    ($($int:ty),*) => {
+        $(
+            impl Monoid<$int> for Gcd {
+                fn combine(a: &$int, b: &$int) -> $int {
+                    let (mut a, mut b) = (*a, *b);
+                    while b != 0 {
+                        let r = a % b;
+                        a = b;
+                        b = r;
+                    }
+                    a
+                }
+            }
+        )*
+    };
+}
+
+impl_gcd!(u8, u16, u32, u64, u128, usize);
+
+/// Stack that keeps, next to each element, the aggregate of that element
+/// and everything below it, so the aggregate of the whole stack is
+/// always on top.
+pub struct MonoidStack<T, M> {
+    stack: Stack<Entry<T>>,
+    marker: PhantomData<M>,
+}
+
+struct Entry<T> {
+    element: T,
+    aggregate: T,
+}
+
+impl<T: Clone, M: Monoid<T>> MonoidStack<T, M> {
+    pub fn new() -> Self {
+        MonoidStack {
+            stack: Stack::new(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    pub fn push(&mut self, element: T) {
+        let aggregate = match self.stack.peek() {
+            Some(top) => M::combine(&top.aggregate, &element),
+            None => element.clone(),
+        };
+        self.stack.push(Entry { element, aggregate });
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.stack.pop().map(|entry| entry.element)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.stack.peek().map(|entry| &entry.element)
+    }
+
+    /// Combination of the elements from the bottom of the stack to the
+    /// top.
+    pub fn aggregate(&self) -> Option<&T> {
+        self.stack.peek().map(|entry| &entry.aggregate)
+    }
+}
+
+impl<T: Clone, M: Monoid<T>> Default for MonoidStack<T, M> {
+    fn default() -> Self {
+        MonoidStack::new()
+    }
+}
+
+/// `M` with its operands swapped.
+struct Flip<M>(PhantomData<M>);
+
+impl<T, M: Monoid<T>> Monoid<T> for Flip<M> {
+    fn combine(a: &T, b: &T) -> T {
+        M::combine(b, a)
+    }
+}
+
+/// Queue made of two `MonoidStack`s: elements are enqueued on `back`
+/// and, whenever `front` runs empty, moved over to it in reverse, so
+/// each element is moved once and operations take O(1) amortized.
+///
+/// `front` pops the oldest element first, so it aggregates with the
+/// operands flipped to keep the combination in queue order.
+pub struct MonoidQueue<T, M> {
+    front: MonoidStack<T, Flip<M>>,
+    back: MonoidStack<T, M>,
+}
+
+impl<T: Clone, M: Monoid<T>> MonoidQueue<T, M> {
+    pub fn new() -> Self {
+        MonoidQueue {
+            front: MonoidStack::new(),
+            back: MonoidStack::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.front.len() + self.back.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.front.is_empty() && self.back.is_empty()
+    }
+
+    pub fn enqueue(&mut self, element: T) {
+        self.back.push(element);
+    }
+
+    pub fn dequeue(&mut self) -> Option<T> {
+        if self.front.is_empty() {
+            while let Some(element) = self.back.pop() {
+                self.front.push(element);
+            }
+        }
+        self.front.pop()
+    }
+
+    /// Combination of the elements from the front of the queue to the
+    /// back.
+    pub fn aggregate(&self) -> Option<T> {
+        match (self.front.aggregate(), self.back.aggregate()) {
+            (Some(front), Some(back)) => Some(M::combine(front, back)),
+            (front, back) => front.or(back).cloned(),
+        }
+    }
+}
+
+impl<T: Clone, M: Monoid<T>> Default for MonoidQueue<T, M> {
+    fn default() -> Self {
+        MonoidQueue::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Concat;
+
+    impl Monoid<String> for Concat {
+        fn combine(a: &String, b: &String) -> String {
+            format!("{}{}", a, b)
+        }
+    }
+
+    #[test]
+    fn test_stack_tracks_min_and_max() {
+        let mut min: MonoidStack<i32, Min> = MonoidStack::new();
+        let mut max: MonoidStack<i32, Max> = MonoidStack::new();
+        assert_eq!(min.aggregate(), None);
+        for &element in &[5, 3, 8, 1, 9] {
+            min.push(element);
+            max.push(element);
+        }
+        assert_eq!((min.aggregate(), max.aggregate()), (Some(&1), Some(&9)));
+        min.pop();
+        max.pop();
+        assert_eq!((min.aggregate(), max.aggregate()), (Some(&1), Some(&8)));
+        min.pop();
+        max.pop();
+        assert_eq!((min.aggregate(), max.aggregate()), (Some(&3), Some(&8)));
+        assert_eq!(min.peek(), Some(&8));
+        assert_eq!(min.len(), 3);
+    }
+
+    #[test]
+    fn test_sliding_window_matches_brute_force() {
+        let samples: Vec<u64> = (0..500u64).map(|i| (i * 7919 + 13) % 101).collect();
+        let window = 16;
+        let mut max: MonoidQueue<u64, Max> = MonoidQueue::new();
+        let mut sum: MonoidQueue<u64, Sum> = MonoidQueue::new();
+        for (i, &sample) in samples.iter().enumerate() {
+            max.enqueue(sample);
+            sum.enqueue(sample);
+            if max.len() > window {
+                max.dequeue();
+                sum.dequeue();
+            }
+            let current = &samples[(i + 1).saturating_sub(window)..=i];
+            assert_eq!(max.aggregate(), current.iter().max().copied());
+            assert_eq!(sum.aggregate(), Some(current.iter().sum()));
+        }
+    }
+
+    #[test]
+    fn test_queue_combines_in_order() {
+        let mut q: MonoidQueue<String, Concat> = MonoidQueue::new();
+        assert_eq!(q.aggregate(), None);
+        for word in "a b c d".split(' ') {
+            q.enqueue(word.to_string());
+        }
+        assert_eq!(q.dequeue(), Some("a".to_string()));
+        q.enqueue("e".to_string());
+        assert_eq!(q.aggregate(), Some("bcde".to_string()));
+        assert_eq!(q.len(), 4);
+    }
+
+    #[test]
+    fn test_gcd() {
+        let mut q: MonoidQueue<u32, Gcd> = MonoidQueue::new();
+        for &element in &[12, 18, 30] {
+            q.enqueue(element);
+        }
+        assert_eq!(q.aggregate(), Some(6));
+        q.dequeue();
+        q.enqueue(25);
+        assert_eq!(q.aggregate(), Some(1));
+        q.dequeue();
+        q.dequeue();
+        assert_eq!(q.aggregate(), Some(25));
+    }
+}
@@ -0,0 +1,227 @@
+use alloc_crate::vec;
+use alloc_crate::vec::Vec;
+
+/// `k` stacks sharing one buffer.
+///
+/// Stack `i` occupies `buff[base[i]..base[i] + len[i]]` and may grow up
+/// to `base[i + 1]`; `base[k]` is the end of the buffer. When a stack
+/// runs into its neighbour, the free space is shared out again among all
+/// stacks and they are moved in place (Knuth, TAOCP 2.2.2). The buffer
+/// only grows, doubling, once there is no free space left at all.
+///
+/// Stacks are numbered from zero; the methods taking a stack number
+/// panic if it is not below `stacks()`.
+pub struct MultiStack<T> {
+    buff: Vec<Option<T>>,
+    base: Vec<usize>,
+    len: Vec<usize>,
+}
+
+const MIN_CAPACITY: usize = 8;
+
+impl<T> MultiStack<T> {
+    /// Panics if `stacks` is zero.
+    pub fn new(stacks: usize) -> Self {
+        MultiStack::with_capacity(stacks, MIN_CAPACITY.max(stacks))
+    }
+
+    /// Panics if `stacks` is zero.
+    pub fn with_capacity(stacks: usize, capacity: usize) -> Self {
+        assert!(stacks > 0, "multi stack must host at least one stack");
+        let mut buff = Vec::with_capacity(capacity);
+        buff.resize_with(capacity, || None);
+        let base = (0..=stacks).map(|i| i * capacity / stacks).collect();
+        MultiStack {
+            buff,
+            base,
+            len: vec![0; stacks],
+        }
+    }
+
+    pub fn stacks(&self) -> usize {
+        self.len.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buff.len()
+    }
+
+    /// Elements across all stacks.
+    pub fn total_len(&self) -> usize {
+        self.len.iter().sum()
+    }
+
+    pub fn len(&self, stack: usize) -> usize {
+        self.check(stack);
+        self.len[stack]
+    }
+
+    pub fn is_empty(&self, stack: usize) -> bool {
+        self.len(stack) == 0
+    }
+
+    pub fn push(&mut self, stack: usize, element: T) {
+        self.check(stack);
+        if self.base[stack] + self.len[stack] == self.base[stack + 1] {
+            if self.total_len() == self.capacity() {
+                let capacity = (self.capacity() * 2).max(MIN_CAPACITY);
+                self.buff.resize_with(capacity, || None);
+                *self.base.last_mut().unwrap() = capacity;
+            }
+            self.repack(stack);
+        }
+        self.buff[self.base[stack] + self.len[stack]] = Some(element);
+        self.len[stack] += 1;
+    }
+
+    pub fn pop(&mut self, stack: usize) -> Option<T> {
+        if self.len(stack) == 0 {
+            return None;
+        }
+        self.len[stack] -= 1;
+        self.buff[self.base[stack] + self.len[stack]].take()
+    }
+
+    pub fn peek(&self, stack: usize) -> Option<&T> {
+        self.top(stack).and_then(|i| self.buff[i].as_ref())
+    }
+
+    pub fn peek_mut(&mut self, stack: usize) -> Option<&mut T> {
+        self.top(stack).and_then(move |i| self.buff[i].as_mut())
+    }
+
+    fn top(&self, stack: usize) -> Option<usize> {
+        (self.len(stack) > 0).then(|| self.base[stack] + self.len[stack] - 1)
+    }
+
+    fn check(&self, stack: usize) {
+        assert!(
+            stack < self.stacks(),
+            "no stack {} among {} stacks",
+            stack,
+            self.stacks()
+        );
+    }
+
+    /// Shares the free space out evenly among the stacks, `full` getting
+    /// the remainder, and moves every stack to its new base. Stacks
+    /// moving down are moved first, lowest first, and stacks moving up
+    /// after, highest first, so no stack overwrites one not moved yet.
+    ///
+    /// There must be at least one free slot.
+    fn repack(&mut self, full: usize) {
+        let stacks = self.stacks();
+        let free = self.capacity() - self.total_len();
+        let share = free / stacks;
+        let mut new_base = Vec::with_capacity(stacks);
+        let mut next = 0;
+        for i in 0..stacks {
+            new_base.push(next);
+            next += self.len[i] + share;
+            if i == full {
+                next += free % stacks;
+            }
+        }
+        for (i, &to) in new_base.iter().enumerate() {
+            if to < self.base[i] {
+                self.move_stack(i, to);
+            }
+        }
+        for (i, &to) in new_base.iter().enumerate().rev() {
+            if to > self.base[i] {
+                self.move_stack(i, to);
+            }
+        }
+    }
+
+    fn move_stack(&mut self, stack: usize, to: usize) {
+        let from = self.base[stack];
+        let len = self.len[stack];
+        if to < from {
+            for i in 0..len {
+                self.buff[to + i] = self.buff[from + i].take();
+            }
+        } else {
+            for i in (0..len).rev() {
+                self.buff[to + i] = self.buff[from + i].take();
+            }
+        }
+        self.base[stack] = to;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::XorShift;
+
+    #[test]
+    fn test_stacks_are_independent() {
+        let mut s: MultiStack<i32> = MultiStack::new(3);
+        s.push(0, 1);
+        s.push(2, 30);
+        s.push(0, 2);
+        assert_eq!(s.pop(1), None);
+        assert_eq!(s.peek(0), Some(&2));
+        assert_eq!(s.len(0), 2);
+        assert_eq!(s.total_len(), 3);
+        if let Some(top) = s.peek_mut(2) {
+            *top += 1;
+        }
+        assert_eq!(s.pop(2), Some(31));
+        assert!(s.is_empty(2));
+        assert_eq!(s.pop(0), Some(2));
+        assert_eq!(s.pop(0), Some(1));
+        assert_eq!(s.pop(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "no stack 2 among 2 stacks")]
+    fn test_push_to_missing_stack_panics() {
+        let mut s = MultiStack::new(2);
+        s.push(2, 0);
+    }
+
+    #[test]
+    fn test_relocates_before_growing() {
+        let mut s: MultiStack<usize> = MultiStack::with_capacity(4, 16);
+        s.push(3, 300);
+        s.push(1, 100);
+        for i in 0..14 {
+            s.push(2, i);
+        }
+        assert_eq!(s.capacity(), 16);
+        s.push(0, 0);
+        assert_eq!(s.capacity(), 32);
+        assert_eq!(s.peek(3), Some(&300));
+        assert_eq!(s.peek(1), Some(&100));
+        assert_eq!(s.peek(0), Some(&0));
+        for i in (0..14).rev() {
+            assert_eq!(s.pop(2), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_matches_separate_stacks() {
+        let mut rng = XorShift::new(0x9e37_79b9_7f4a_7c15);
+        let mut s = MultiStack::with_capacity(5, 1);
+        let mut model = vec![Vec::new(); 5];
+        for i in 0..10_000 {
+            let stack = rng.below(5) as usize;
+            if rng.below(3) == 0 {
+                assert_eq!(s.pop(stack), model[stack].pop());
+            } else {
+                s.push(stack, i);
+                model[stack].push(i);
+            }
+            assert_eq!(s.peek(stack), model[stack].last());
+            assert_eq!(s.len(stack), model[stack].len());
+        }
+        for (stack, model) in model.iter_mut().enumerate() {
+            while let Some(element) = model.pop() {
+                assert_eq!(s.pop(stack), Some(element));
+            }
+        }
+        assert_eq!(s.total_len(), 0);
+    }
+}
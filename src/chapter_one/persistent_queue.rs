@@ -0,0 +1,360 @@
+use super::persistent_stack::PersistentStack;
+use alloc_crate::rc::Rc;
+use alloc_crate::vec;
+use alloc_crate::vec::Vec;
+use core::cell::{Cell, OnceCell};
+
+/// Immutable queue with O(1) worst-case `enqueue` and `dequeue`
+/// (Okasaki's real-time queue).
+///
+/// Elements sit in a lazy `front` stream followed by a strict `rear`
+/// stack in reverse order. When `rear` outgrows `front`, the two are
+/// rotated into a new front stream, `front ++ reverse(rear)`, but the
+/// rotation is only suspended: each of its cells is computed in O(1)
+/// when first forced. The `schedule` points into the front stream, and
+/// every operation forces one more cell of it, so the rotation is done
+/// by the time the next one starts. Forced cells are shared by every
+/// version, which is what keeps the bounds worst-case instead of
+/// amortized under persistent use.
+///
+/// Invariant: `schedule` holds `front_len - rear.len()` cells.
+pub struct PersistentQueue<T> {
+    front: Stream<T>,
+    front_len: usize,
+    rear: PersistentStack<Rc<T>>,
+    schedule: Stream<T>,
+}
+
+/// Lazy list; `None` is the empty stream.
+struct Stream<T>(Option<Rc<Lazy<T>>>);
+
+/// A stream cell, either computed or still holding the rotation step
+/// that computes it.
+struct Lazy<T> {
+    value: OnceCell<(Rc<T>, Stream<T>)>,
+    suspension: Cell<Option<Rotate<T>>>,
+}
+
+/// `front ++ reverse(rear) ++ acc`, where `rear` holds exactly one
+/// element more than `front`.
+struct Rotate<T> {
+    front: Stream<T>,
+    rear: PersistentStack<Rc<T>>,
+    acc: Stream<T>,
+}
+
+impl<T> Stream<T> {
+    fn empty() -> Self {
+        Stream(None)
+    }
+
+    fn cons(element: Rc<T>, rest: Stream<T>) -> Self {
+        Stream(Some(Rc::new(Lazy {
+            value: OnceCell::from((element, rest)),
+            suspension: Cell::new(None),
+        })))
+    }
+
+    fn suspend(rotate: Rotate<T>) -> Self {
+        Stream(Some(Rc::new(Lazy {
+            value: OnceCell::new(),
+            suspension: Cell::new(Some(rotate)),
+        })))
+    }
+
+    /// The first element and the rest of the stream, computing them if
+    /// this is the first time. Rotations only run once `front` has been
+    /// forced entirely, so this never recurses more than one level.
+    fn force(&self) -> Option<&(Rc<T>, Stream<T>)> {
+        let lazy = self.0.as_ref()?;
+        Some(lazy.value.get_or_init(|| {
+            let Rotate { front, rear, acc } = lazy
+                .suspension
+                .take()
+                .expect("unforced cell has a suspension");
+            let last = Rc::clone(rear.peek().expect("rear is longer than front"));
+            match front.force() {
+                None => (last, acc),
+                Some((element, rest)) => (
+                    Rc::clone(element),
+                    Stream::suspend(Rotate {
+                        front: rest.clone(),
+                        rear: rear.pop().expect("rear is longer than front"),
+                        acc: Stream::cons(last, acc),
+                    }),
+                ),
+            }
+        }))
+    }
+}
+
+impl<T> Clone for Stream<T> {
+    fn clone(&self) -> Self {
+        Stream(self.0.clone())
+    }
+}
+
+/// Frees the cells no other stream shares with an explicit work list:
+/// both the forced tail and a pending rotation can be arbitrarily long.
+impl<T> Drop for Stream<T> {
+    fn drop(&mut self) {
+        let mut pending = vec![self.0.take()];
+        while let Some(link) = pending.pop() {
+            let mut lazy = match link.map(Rc::try_unwrap) {
+                Some(Ok(lazy)) => lazy,
+                _ => continue,
+            };
+            if let Some((_, mut rest)) = lazy.value.take() {
+                pending.push(rest.0.take());
+            }
+            if let Some(mut rotate) = lazy.suspension.take() {
+                pending.push(rotate.front.0.take());
+                pending.push(rotate.acc.0.take());
+            }
+        }
+    }
+}
+
+impl<T> PersistentQueue<T> {
+    pub fn new() -> Self {
+        PersistentQueue {
+            front: Stream::empty(),
+            front_len: 0,
+            rear: PersistentStack::new(),
+            schedule: Stream::empty(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.front_len + self.rear.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.front_len == 0
+    }
+
+    /// The version with `element` at the back of this one.
+    pub fn enqueue(&self, element: T) -> Self {
+        PersistentQueue::exec(
+            self.front.clone(),
+            self.front_len,
+            self.rear.push(Rc::new(element)),
+            self.schedule.clone(),
+        )
+    }
+
+    /// The version without the front element, or `None` if this one is
+    /// empty.
+    pub fn dequeue(&self) -> Option<Self> {
+        self.front.force().map(|(_, rest)| {
+            PersistentQueue::exec(
+                rest.clone(),
+                self.front_len - 1,
+                self.rear.clone(),
+                self.schedule.clone(),
+            )
+        })
+    }
+
+    /// The element `dequeue` would remove.
+    pub fn peek(&self) -> Option<&T> {
+        self.front.force().map(|(element, _)| &**element)
+    }
+
+    /// Iterates from the front of the queue to the back. Creating the
+    /// iterator takes O(`rear` length) to reverse the rear stack.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut rear: Vec<&T> = self.rear.iter().map(|element| &**element).collect();
+        rear.reverse();
+        Iter {
+            front: &self.front,
+            rear: rear.into_iter(),
+            len: self.len(),
+        }
+    }
+
+    /// Forces one cell of the schedule, or starts a new rotation once
+    /// the schedule is exhausted, which is exactly when `rear` holds one
+    /// element more than `front`.
+    fn exec(
+        front: Stream<T>,
+        front_len: usize,
+        rear: PersistentStack<Rc<T>>,
+        schedule: Stream<T>,
+    ) -> Self {
+        if let Some((_, rest)) = schedule.force() {
+            return PersistentQueue {
+                front,
+                front_len,
+                rear,
+                schedule: rest.clone(),
+            };
+        }
+        let front_len = front_len + rear.len();
+        let front = Stream::suspend(Rotate {
+            front,
+            rear,
+            acc: Stream::empty(),
+        });
+        PersistentQueue {
+            front: front.clone(),
+            front_len,
+            rear: PersistentStack::new(),
+            schedule: front,
+        }
+    }
+}
+
+pub struct Iter<'a, T> {
+    front: &'a Stream<T>,
+    rear: vec::IntoIter<&'a T>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let element = match self.front.force() {
+            Some((element, rest)) => {
+                self.front = rest;
+                &**element
+            }
+            None => self.rear.next()?,
+        };
+        self.len -= 1;
+        Some(element)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a PersistentQueue<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> Clone for PersistentQueue<T> {
+    fn clone(&self) -> Self {
+        PersistentQueue {
+            front: self.front.clone(),
+            front_len: self.front_len,
+            rear: self.rear.clone(),
+            schedule: self.schedule.clone(),
+        }
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for PersistentQueue<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for PersistentQueue<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for PersistentQueue<T> {}
+
+impl<T> Default for PersistentQueue<T> {
+    fn default() -> Self {
+        PersistentQueue::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{DropCounter, XorShift};
+    use std::collections::VecDeque;
+
+    fn schedule_len<T>(q: &PersistentQueue<T>) -> usize {
+        let mut len = 0;
+        let mut stream = &q.schedule;
+        while let Some((_, rest)) = stream.force() {
+            len += 1;
+            stream = rest;
+        }
+        len
+    }
+
+    #[test]
+    fn test_enqueue_and_dequeue_element() {
+        let empty: PersistentQueue<i32> = PersistentQueue::new();
+        let q = empty.enqueue(1).enqueue(2).enqueue(3);
+        assert_eq!(q.len(), 3);
+        assert_eq!(q.peek(), Some(&1));
+        let rest = q.dequeue().unwrap();
+        assert_eq!(rest.peek(), Some(&2));
+        assert_eq!(q.peek(), Some(&1));
+        assert_eq!(q.iter().collect::<Vec<_>>(), [&1, &2, &3]);
+        assert_eq!(rest.enqueue(4).iter().collect::<Vec<_>>(), [&2, &3, &4]);
+        assert!(empty.is_empty());
+        assert!(empty.dequeue().is_none());
+        assert_eq!(format!("{:?}", rest), "[2, 3]");
+    }
+
+    /// Applies random operations to random earlier versions and checks
+    /// each result against a `VecDeque` model.
+    #[test]
+    fn test_random_versions_match_model() {
+        let mut rng = XorShift::new(0x2545_f491_4f6c_dd1d);
+        let mut versions = vec![(PersistentQueue::new(), VecDeque::new())];
+        for i in 0..2_000 {
+            let (q, model) = &versions[rng.next_u64() as usize % versions.len()];
+            let (q, mut model) = (q.clone(), model.clone());
+            let q = if rng.below(3) == 0 {
+                model.pop_front();
+                q.dequeue().unwrap_or(q)
+            } else {
+                model.push_back(i);
+                q.enqueue(i)
+            };
+            assert_eq!(q.len(), model.len());
+            assert_eq!(q.peek(), model.front());
+            assert_eq!(schedule_len(&q), q.front_len - q.rear.len());
+            versions.push((q, model));
+        }
+        for (q, model) in &versions {
+            assert!(q.iter().eq(model.iter()));
+        }
+    }
+
+    #[test]
+    fn test_drop_long_queue() {
+        let mut q: PersistentQueue<u64> = PersistentQueue::new();
+        for i in 0..1_000_000 {
+            q = q.enqueue(i);
+        }
+        let half = (0..500_000).fold(q.clone(), |q, _| q.dequeue().unwrap());
+        assert_eq!(half.peek(), Some(&500_000));
+        drop(q);
+        assert_eq!(half.len(), 500_000);
+    }
+
+    #[test]
+    fn test_drop_releases_elements() {
+        let counter = DropCounter::new();
+        {
+            let mut q = PersistentQueue::new();
+            for _ in 0..100 {
+                q = q.enqueue(counter.token());
+            }
+            let rest = q.dequeue().unwrap();
+            assert_eq!(counter.alive(), 100);
+            assert_eq!(rest.len(), 99);
+        }
+        assert_eq!(counter.alive(), 0);
+    }
+}
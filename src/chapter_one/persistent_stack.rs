@@ -0,0 +1,290 @@
+use alloc_crate::rc::Rc;
+use alloc_crate::sync::Arc;
+use core::ops::Deref;
+
+/// Reference-counted pointer a `PersistentStack` shares its nodes with.
+pub trait PointerKind {
+    type Pointer<U>: Deref<Target = U> + Clone;
+
+    fn new<U>(value: U) -> Self::Pointer<U>;
+
+    /// Drops `pointer`, returning the value if it was the last reference.
+    /// When several references are dropped at once, exactly one of them
+    /// gets the value.
+    fn into_inner<U>(pointer: Self::Pointer<U>) -> Option<U>;
+
+    fn ptr_eq<U>(a: &Self::Pointer<U>, b: &Self::Pointer<U>) -> bool;
+}
+
+/// Shares nodes through `Rc`: cheapest, but confined to one thread.
+pub struct RcPointer;
+
+/// Shares nodes through `Arc`, so versions can be sent across threads.
+pub struct ArcPointer;
+
+impl PointerKind for RcPointer {
+    type Pointer<U> = Rc<U>;
+
+    fn new<U>(value: U) -> Rc<U> {
+        Rc::new(value)
+    }
+
+    fn into_inner<U>(pointer: Rc<U>) -> Option<U> {
+        Rc::into_inner(pointer)
+    }
+
+    fn ptr_eq<U>(a: &Rc<U>, b: &Rc<U>) -> bool {
+        Rc::ptr_eq(a, b)
+    }
+}
+
+impl PointerKind for ArcPointer {
+    type Pointer<U> = Arc<U>;
+
+    fn new<U>(value: U) -> Arc<U> {
+        Arc::new(value)
+    }
+
+    fn into_inner<U>(pointer: Arc<U>) -> Option<U> {
+        Arc::into_inner(pointer)
+    }
+
+    fn ptr_eq<U>(a: &Arc<U>, b: &Arc<U>) -> bool {
+        Arc::ptr_eq(a, b)
+    }
+}
+
+type Link<T, P> = Option<<P as PointerKind>::Pointer<Node<T, P>>>;
+
+/// Immutable linked stack whose versions share their common tail.
+///
+/// `push` and `pop` leave `self` untouched and return a new version in
+/// O(1), so every earlier version stays valid. Cloning a version only
+/// clones a pointer.
+pub struct PersistentStack<T, P: PointerKind = RcPointer> {
+    head: Link<T, P>,
+    size: usize,
+}
+
+struct Node<T, P: PointerKind> {
+    element: T,
+    next: Link<T, P>,
+}
+
+impl<T, P: PointerKind> PersistentStack<T, P> {
+    pub fn new() -> Self {
+        PersistentStack {
+            head: None,
+            size: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    /// The version with `element` on top of this one.
+    pub fn push(&self, element: T) -> Self {
+        PersistentStack {
+            head: Some(P::new(Node {
+                element,
+                next: self.head.clone(),
+            })),
+            size: self.size + 1,
+        }
+    }
+
+    /// The version below the top element, or `None` if this one is
+    /// empty.
+    pub fn pop(&self) -> Option<Self> {
+        self.head.as_ref().map(|node| PersistentStack {
+            head: node.next.clone(),
+            size: self.size - 1,
+        })
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.element)
+    }
+
+    /// Whether both versions are the same list, regardless of how they
+    /// were obtained.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.head, &other.head) {
+            (Some(a), Some(b)) => P::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Iterates from the top of the stack to the bottom.
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter {
+            next: self.head.as_deref(),
+            len: self.size,
+        }
+    }
+}
+
+pub struct Iter<'a, T, P: PointerKind> {
+    next: Option<&'a Node<T, P>>,
+    len: usize,
+}
+
+impl<'a, T, P: PointerKind> Iterator for Iter<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|node| {
+            self.next = node.next.as_deref();
+            self.len -= 1;
+            &node.element
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T, P: PointerKind> ExactSizeIterator for Iter<'_, T, P> {}
+
+impl<'a, T, P: PointerKind> IntoIterator for &'a PersistentStack<T, P> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, P>;
+
+    fn into_iter(self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T, P: PointerKind> Clone for PersistentStack<T, P> {
+    fn clone(&self) -> Self {
+        PersistentStack {
+            head: self.head.clone(),
+            size: self.size,
+        }
+    }
+}
+
+impl<T: core::fmt::Debug, P: PointerKind> core::fmt::Debug for PersistentStack<T, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, P: PointerKind> PartialEq for PersistentStack<T, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, P: PointerKind> Eq for PersistentStack<T, P> {}
+
+impl<T, P: PointerKind> Default for PersistentStack<T, P> {
+    fn default() -> Self {
+        PersistentStack::new()
+    }
+}
+
+/// Frees the nodes no other version shares, one at a time, and stops at
+/// the first shared one. Versions dropped on several threads at once
+/// still free a shared tail in a single loop, on whichever thread lets
+/// go of it last.
+impl<T, P: PointerKind> Drop for PersistentStack<T, P> {
+    fn drop(&mut self) {
+        let mut link = self.head.take();
+        while let Some(mut node) = link.and_then(P::into_inner) {
+            link = node.next.take();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_versions_are_preserved() {
+        let empty: PersistentStack<i32> = PersistentStack::new();
+        let one = empty.push(1);
+        let two = one.push(2);
+        let other = one.push(20);
+        assert!(empty.is_empty());
+        assert_eq!(one.iter().collect::<Vec<_>>(), [&1]);
+        assert_eq!(two.iter().collect::<Vec<_>>(), [&2, &1]);
+        assert_eq!(other.iter().collect::<Vec<_>>(), [&20, &1]);
+        assert_eq!(two.peek(), Some(&2));
+        assert_eq!(two.len(), 2);
+        assert!(two.pop().unwrap().ptr_eq(&one));
+        assert!(other.pop().unwrap().ptr_eq(&one));
+        assert!(empty.pop().is_none());
+    }
+
+    #[test]
+    fn test_shared_nodes_outlive_dropped_versions() {
+        let element = Rc::new(());
+        let base: PersistentStack<Rc<()>> = PersistentStack::new().push(Rc::clone(&element));
+        let top = base.push(Rc::clone(&element)).push(Rc::clone(&element));
+        assert_eq!(Rc::strong_count(&element), 4);
+        drop(base);
+        assert_eq!(Rc::strong_count(&element), 4);
+        assert_eq!(top.len(), 3);
+        drop(top);
+        assert_eq!(Rc::strong_count(&element), 1);
+    }
+
+    #[test]
+    fn test_drop_long_stack() {
+        let mut s: PersistentStack<u64> = PersistentStack::new();
+        for i in 0..1_000_000 {
+            s = s.push(i);
+        }
+        assert_eq!(s.len(), 1_000_000);
+    }
+
+    #[test]
+    fn test_arc_versions_cross_threads() {
+        let base: PersistentStack<i32, ArcPointer> = PersistentStack::new().push(1).push(2);
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                let base = base.clone();
+                thread::spawn(move || base.push(i))
+            })
+            .collect();
+        for (i, worker) in workers.into_iter().enumerate() {
+            let version = worker.join().unwrap();
+            assert_eq!(
+                version.iter().copied().collect::<Vec<_>>(),
+                [i as i32, 2, 1]
+            );
+            assert!(version.pop().unwrap().ptr_eq(&base));
+        }
+        assert_eq!(format!("{:?}", base), "[2, 1]");
+    }
+
+    #[test]
+    fn test_concurrent_drop_of_long_shared_tail() {
+        for _ in 0..5 {
+            let mut base: PersistentStack<u64, ArcPointer> = PersistentStack::new();
+            for i in 0..200_000 {
+                base = base.push(i);
+            }
+            let workers: Vec<_> = (0..4)
+                .map(|i| {
+                    let version = base.push(i);
+                    thread::spawn(move || drop(version))
+                })
+                .collect();
+            drop(base);
+            for worker in workers {
+                worker.join().unwrap();
+            }
+        }
+    }
+}
@@ -1,58 +1,414 @@
-/// After trying to implement a queue using a doubly linked
-/// list, I surrendered. That's why I moved towards a vector
-/// implementation.
-#[derive(Debug)]
-struct Queue<T> {
-    buff: Vec<T>,
+use alloc_crate::boxed::Box;
+use alloc_crate::vec::Vec;
+use core::iter::FromIterator;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+/// Array based queue.
+///
+/// The vector is used as a circular buffer: `head` points to the
+/// oldest element and the next free slot is `head + size` (modulo
+/// the capacity). Slots are only ever filled in order, so a slot
+/// past `buff.len()` is simply pushed instead of overwritten. The
+/// buffer grows when full and shrinks when it drops to a quarter
+/// of its capacity, so only those two operations copy elements. It
+/// never shrinks below `min_capacity`.
+pub struct Queue<T> {
+    buff: Vec<Option<T>>,
+    head: usize,
     size: usize,
-    current_element: usize,
+    min_capacity: usize,
 }
 
-impl<T> Queue<T>
-where
-    T: Clone + std::fmt::Debug,
-{
-    #[allow(dead_code)]
-    fn new() -> Self {
+const MIN_CAPACITY: usize = 8;
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        Queue::with_capacity(MIN_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
         Queue {
-            buff: Vec::with_capacity(100),
+            buff: Vec::with_capacity(capacity.max(MIN_CAPACITY)),
+            head: 0,
             size: 0,
-            current_element: 0,
+            min_capacity: MIN_CAPACITY,
+        }
+    }
+
+    /// Like `with_capacity`, but the buffer never shrinks below
+    /// `capacity`, so a queue kept within it never reallocates.
+    pub fn with_min_capacity(capacity: usize) -> Self {
+        Queue {
+            min_capacity: capacity.max(MIN_CAPACITY),
+            ..Queue::with_capacity(capacity)
         }
     }
 
-    #[allow(dead_code)]
-    fn is_empty(&self) -> bool {
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
         self.size == 0
     }
 
-    #[allow(dead_code)]
-    fn enqueue(&mut self, element: T) {
-        if self.buff.capacity() == self.size {
-            self.buff.reserve(self.size * 2);
+    pub fn capacity(&self) -> usize {
+        self.buff.capacity()
+    }
+
+    pub fn enqueue(&mut self, element: T) {
+        if self.size == self.capacity() {
+            self.resize(self.capacity() * 2);
+        }
+        let tail = (self.head + self.size) % self.capacity();
+        if tail == self.buff.len() {
+            self.buff.push(Some(element));
+        } else {
+            self.buff[tail] = Some(element);
         }
-        self.buff.insert(self.size, element);
         self.size += 1;
     }
 
-    #[allow(dead_code)]
-    fn dequeue(&mut self) -> Option<T> {
+    pub fn dequeue(&mut self) -> Option<T> {
         if self.is_empty() {
             return None;
         }
-        let result = self.buff.get(self.current_element).cloned();
-        self.current_element += 1;
-        if self.current_element > 1000 {
-            let size = self.size - self.current_element;
-            let mut v: Vec<T> = Vec::with_capacity(self.size - self.current_element + 1000);
-            v.extend_from_slice(&self.buff[self.current_element..self.size]);
-            self.buff = v;
-            self.current_element = 0;
-            self.size = size;
+        let result = self.buff[self.head].take();
+        self.head = (self.head + 1) % self.capacity();
+        self.size -= 1;
+        if self.capacity() > self.min_capacity && self.size <= self.capacity() / 4 {
+            self.resize(self.capacity() / 2);
         }
         result
     }
+
+    pub fn peek(&self) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.buff[self.head].as_ref()
+    }
+
+    /// Moves the elements, oldest first, to the start of a new buffer.
+    fn resize(&mut self, capacity: usize) {
+        let capacity = capacity.max(self.min_capacity);
+        let old_capacity = self.capacity();
+        let mut buff = Vec::with_capacity(capacity);
+        for i in 0..self.size {
+            buff.push(self.buff[(self.head + i) % old_capacity].take());
+        }
+        self.buff = buff;
+        self.head = 0;
+    }
+}
+
+impl<T> Queue<T> {
+    /// Iterates from the front of the queue to the back.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let (front, back) = self.ranges();
+        Iter {
+            front: self.buff[front].iter(),
+            back: self.buff[back].iter(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        let (front, back) = self.ranges();
+        let (low, high) = self.buff.split_at_mut(front.start);
+        IterMut {
+            front: high[..front.end - front.start].iter_mut(),
+            back: low[back].iter_mut(),
+        }
+    }
+
+    /// Dequeues every element, front first. Elements not consumed by
+    /// the iterator are dropped along with it.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain { queue: self }
+    }
+
+    /// The occupied slots: from `head` towards the end of the buffer,
+    /// then from the start of the buffer if they wrap around.
+    fn ranges(&self) -> (core::ops::Range<usize>, core::ops::Range<usize>) {
+        let end = self.head + self.size;
+        if end <= self.capacity() {
+            (self.head..end, 0..0)
+        } else {
+            (self.head..self.capacity(), 0..end - self.capacity())
+        }
+    }
+}
+
+pub struct Iter<'a, T> {
+    front: core::slice::Iter<'a, Option<T>>,
+    back: core::slice::Iter<'a, Option<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot = self.front.next().or_else(|| self.back.next())?;
+        slot.as_ref()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.front.len() + self.back.len();
+        (len, Some(len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+pub struct IterMut<'a, T> {
+    front: core::slice::IterMut<'a, Option<T>>,
+    back: core::slice::IterMut<'a, Option<T>>,
 }
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot = self.front.next().or_else(|| self.back.next())?;
+        slot.as_mut()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.front.len() + self.back.len();
+        (len, Some(len))
+    }
+}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+
+pub struct IntoIter<T>(Queue<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.dequeue()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len(), Some(self.0.len()))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+pub struct Drain<'a, T> {
+    queue: &'a mut Queue<T>,
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.queue.dequeue()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.queue.len(), Some(self.queue.len()))
+    }
+}
+
+impl<T> ExactSizeIterator for Drain<'_, T> {}
+
+impl<T> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        for _ in self {}
+    }
+}
+
+impl<T> IntoIterator for Queue<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Queue<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut Queue<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl<T> FromIterator<T> for Queue<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut queue = Queue::with_capacity(iter.size_hint().0);
+        queue.extend(iter);
+        queue
+    }
+}
+
+impl<T> Extend<T> for Queue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for element in iter {
+            self.enqueue(element);
+        }
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for Queue<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Clone> Clone for Queue<T> {
+    fn clone(&self) -> Self {
+        let mut queue = Queue::with_capacity(self.len());
+        queue.extend(self.iter().cloned());
+        queue
+    }
+}
+
+impl<T: PartialEq> PartialEq for Queue<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for Queue<T> {}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Queue::new()
+    }
+}
+
+/// Singly linked queue: elements are dequeued at `head` and
+/// enqueued after `tail`, so both operations are O(1).
+///
+/// The queue owns every node of the chain starting at `head`; `tail`
+/// only aliases the last one. The links are raw pointers to leaked
+/// boxes rather than `Option<Box<Node<T>>>`, because moving a `Box`
+/// asserts that it is the only pointer to its node, which would
+/// invalidate `tail`.
+pub struct LinkedQueue<T> {
+    head: Link<T>,
+    tail: Link<T>,
+    size: usize,
+    marker: PhantomData<Box<Node<T>>>,
+}
+
+type Link<T> = Option<NonNull<Node<T>>>;
+
+struct Node<T> {
+    element: T,
+    next: Link<T>,
+}
+
+impl<T> LinkedQueue<T> {
+    pub fn new() -> Self {
+        LinkedQueue {
+            head: None,
+            tail: None,
+            size: 0,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn enqueue(&mut self, element: T) {
+        let node = Box::new(Node {
+            element,
+            next: None,
+        });
+        let node = NonNull::from(Box::leak(node));
+        match self.tail {
+            // SAFETY: `tail` points to the last node of the chain owned by `self`.
+            Some(mut tail) => unsafe { tail.as_mut().next = Some(node) },
+            None => self.head = Some(node),
+        }
+        self.tail = Some(node);
+        self.size += 1;
+    }
+
+    pub fn dequeue(&mut self) -> Option<T> {
+        self.head.map(|head| {
+            // SAFETY: `head` was leaked from a `Box` in `enqueue` and,
+            // once unlinked here, nothing else points to it.
+            let node = unsafe { Box::from_raw(head.as_ptr()) };
+            self.head = node.next;
+            if self.head.is_none() {
+                self.tail = None;
+            }
+            self.size -= 1;
+            node.element
+        })
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        // SAFETY: the node is owned by `self`, which is borrowed for the
+        // lifetime of the returned reference.
+        self.head.map(|head| unsafe { &(*head.as_ptr()).element })
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        // SAFETY: as in `peek`, with `self` borrowed mutably.
+        self.head
+            .map(|head| unsafe { &mut (*head.as_ptr()).element })
+    }
+
+    /// Moves every element of `other` to the back of `self` in O(1),
+    /// leaving `other` empty.
+    pub fn append(&mut self, other: &mut LinkedQueue<T>) {
+        let other_head = match other.head.take() {
+            None => return,
+            Some(other_head) => other_head,
+        };
+        match self.tail {
+            // SAFETY: `tail` points to the last node of the chain owned by `self`.
+            Some(mut tail) => unsafe { tail.as_mut().next = Some(other_head) },
+            None => self.head = Some(other_head),
+        }
+        self.tail = other.tail.take();
+        self.size += other.size;
+        other.size = 0;
+    }
+}
+
+impl<T> Default for LinkedQueue<T> {
+    fn default() -> Self {
+        LinkedQueue::new()
+    }
+}
+
+impl<T> Drop for LinkedQueue<T> {
+    fn drop(&mut self) {
+        while self.dequeue().is_some() {}
+    }
+}
+
+// SAFETY: `LinkedQueue` owns its nodes just like a `Box` chain would.
+unsafe impl<T: Send> Send for LinkedQueue<T> {}
+unsafe impl<T: Sync> Sync for LinkedQueue<T> {}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -61,7 +417,7 @@ mod test {
     fn test_enqueue_element() {
         let mut q: Queue<i32> = Queue::new();
         q.enqueue(2);
-        assert_eq!(q.is_empty(), false);
+        assert!(!q.is_empty());
     }
 
     #[test]
@@ -70,7 +426,7 @@ mod test {
         q.enqueue(2);
         q.enqueue(20);
         q.enqueue(2000);
-        assert_eq!(q.is_empty(), false);
+        assert!(!q.is_empty());
         assert_eq!(q.dequeue(), Some(2));
         assert_eq!(q.dequeue(), Some(20));
         assert_eq!(q.dequeue(), Some(2000));
@@ -88,4 +444,153 @@ mod test {
         let mut q: Queue<i32> = Queue::new();
         assert_eq!(q.dequeue(), None);
     }
+
+    #[test]
+    fn test_wrap_around_keeps_order() {
+        let mut q: Queue<usize> = Queue::new();
+        let capacity = q.capacity();
+        for round in 0..10 {
+            for i in 0..capacity - 1 {
+                q.enqueue(round * capacity + i);
+            }
+            for i in 0..capacity - 1 {
+                assert_eq!(q.peek(), Some(&(round * capacity + i)));
+                assert_eq!(q.dequeue(), Some(round * capacity + i));
+            }
+        }
+        assert_eq!(q.capacity(), capacity);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_grow_and_shrink() {
+        let mut q: Queue<String> = Queue::new();
+        for i in 0..10_000 {
+            q.enqueue(i.to_string());
+        }
+        assert_eq!(q.len(), 10_000);
+        assert!(q.capacity() >= 10_000);
+        for i in 0..9_990 {
+            assert_eq!(q.dequeue(), Some(i.to_string()));
+        }
+        assert!(q.capacity() <= 4 * q.len().max(MIN_CAPACITY));
+        for i in 9_990..10_000 {
+            assert_eq!(q.dequeue(), Some(i.to_string()));
+        }
+        assert_eq!(q.dequeue(), None);
+    }
+
+    #[test]
+    fn test_linked_enqueue_and_dequeue() {
+        let mut q: LinkedQueue<i32> = LinkedQueue::new();
+        assert_eq!(q.dequeue(), None);
+        q.enqueue(2);
+        q.enqueue(20);
+        assert_eq!(q.peek(), Some(&2));
+        assert_eq!(q.dequeue(), Some(2));
+        q.enqueue(2000);
+        if let Some(front) = q.peek_mut() {
+            *front += 1;
+        }
+        assert_eq!(q.len(), 2);
+        assert_eq!(q.dequeue(), Some(21));
+        assert_eq!(q.dequeue(), Some(2000));
+        assert_eq!(q.dequeue(), None);
+        assert!(q.is_empty());
+        q.enqueue(7);
+        assert_eq!(q.dequeue(), Some(7));
+    }
+
+    #[test]
+    fn test_linked_append() {
+        let mut q: LinkedQueue<String> = LinkedQueue::new();
+        let mut other: LinkedQueue<String> = LinkedQueue::new();
+        q.append(&mut other);
+        assert!(q.is_empty());
+        for i in 0..3 {
+            other.enqueue(i.to_string());
+        }
+        q.append(&mut other);
+        assert!(other.is_empty());
+        assert_eq!(other.dequeue(), None);
+        for i in 3..6 {
+            other.enqueue(i.to_string());
+        }
+        q.append(&mut other);
+        q.enqueue(6.to_string());
+        assert_eq!(q.len(), 7);
+        for i in 0..7 {
+            assert_eq!(q.dequeue(), Some(i.to_string()));
+        }
+        assert_eq!(q.dequeue(), None);
+    }
+
+    /// The sequence a `Box` chain with a raw `tail` gets wrong under
+    /// Stacked Borrows: moving the box that owns the last node, or the
+    /// queue holding it, invalidates `tail`. Run it with
+    /// `cargo +nightly miri test linked`.
+    #[test]
+    fn test_linked_tail_survives_moves() {
+        fn pass<T>(q: LinkedQueue<T>) -> LinkedQueue<T> {
+            q
+        }
+        let mut q = LinkedQueue::new();
+        q.enqueue(1);
+        let mut q = pass(q);
+        q.enqueue(2);
+        assert_eq!(q.dequeue(), Some(1));
+        q.enqueue(3);
+        let mut other = LinkedQueue::new();
+        other.enqueue(4);
+        q.append(&mut other);
+        other.enqueue(5);
+        q.enqueue(6);
+        let rest: Vec<_> = std::iter::from_fn(|| q.dequeue()).collect();
+        assert_eq!(rest, [2, 3, 4, 6]);
+        assert_eq!(other.dequeue(), Some(5));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_linked_drop_long_queue() {
+        let mut q: LinkedQueue<Vec<u8>> = LinkedQueue::new();
+        for _ in 0..1_000_000 {
+            q.enqueue(vec![0]);
+        }
+    }
+
+    #[test]
+    fn test_iterators() {
+        let mut q: Queue<usize> = Queue::new();
+        for i in 0..MIN_CAPACITY {
+            q.enqueue(i);
+        }
+        q.dequeue();
+        q.enqueue(MIN_CAPACITY);
+        for element in &mut q {
+            *element *= 10;
+        }
+        let expected: Vec<_> = (1..=MIN_CAPACITY).map(|i| i * 10).collect();
+        assert_eq!(q.iter().len(), MIN_CAPACITY);
+        assert_eq!(q.iter().copied().collect::<Vec<_>>(), expected);
+        assert_eq!(q.into_iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_clone_eq_debug_default() {
+        let mut q: Queue<String> = ["a", "b"].iter().map(|e| e.to_string()).collect();
+        q.extend(vec!["c".to_string()]);
+        let copy = q.clone();
+        assert_eq!(copy, q);
+        assert_eq!(format!("{:?}", copy), r#"["a", "b", "c"]"#);
+        assert_ne!(copy, Queue::default());
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut q: Queue<i32> = (0..100).collect();
+        assert_eq!(q.drain().take(3).collect::<Vec<_>>(), [0, 1, 2]);
+        assert!(q.is_empty());
+        assert_eq!(q.capacity(), MIN_CAPACITY);
+    }
 }
@@ -0,0 +1,204 @@
+use alloc_crate::vec::Vec;
+
+/// Array based queue that never reallocates in a single enqueue
+/// (Brass 1.5).
+///
+/// Same technique as `ShadowStack`: once `current` is half full, a
+/// `shadow` ring of twice its capacity is allocated, and every following
+/// enqueue also moves the two oldest elements not yet copied into it.
+/// Since elements leave from the front, the copied elements are always
+/// the oldest ones: the first `shadow.size` elements of `current` are
+/// `None`, and dequeue takes them from the shadow instead.
+pub struct ShadowQueue<T> {
+    current: Ring<T>,
+    shadow: Option<Ring<T>>,
+}
+
+/// Fixed capacity circular buffer, filled lazily like `Queue`'s: a slot
+/// past `buff.len()` is pushed instead of overwritten, so allocating a
+/// ring does not touch its slots.
+struct Ring<T> {
+    buff: Vec<Option<T>>,
+    head: usize,
+    size: usize,
+}
+
+const MIN_CAPACITY: usize = 4;
+
+impl<T> Ring<T> {
+    fn with_capacity(capacity: usize) -> Self {
+        Ring {
+            buff: Vec::with_capacity(capacity),
+            head: 0,
+            size: 0,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.buff.capacity()
+    }
+
+    fn slot(&self, index: usize) -> usize {
+        (self.head + index) % self.capacity()
+    }
+
+    /// Must only be called when the ring is not full.
+    fn push_back(&mut self, element: Option<T>) {
+        let tail = self.slot(self.size);
+        if tail == self.buff.len() {
+            self.buff.push(element);
+        } else {
+            self.buff[tail] = element;
+        }
+        self.size += 1;
+    }
+
+    /// Must only be called when the ring is not empty.
+    fn pop_front(&mut self) -> Option<T> {
+        let element = self.buff[self.head].take();
+        self.head = self.slot(1);
+        self.size -= 1;
+        element
+    }
+
+    fn front(&self) -> Option<&T> {
+        if self.size == 0 {
+            return None;
+        }
+        self.buff[self.head].as_ref()
+    }
+}
+
+impl<T> ShadowQueue<T> {
+    pub fn new() -> Self {
+        ShadowQueue::with_capacity(MIN_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        ShadowQueue {
+            current: Ring::with_capacity(capacity.max(MIN_CAPACITY)),
+            shadow: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.current.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current.size == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.current.capacity()
+    }
+
+    pub fn enqueue(&mut self, element: T) {
+        if self.current.size == self.current.capacity() {
+            let shadow = self.shadow.take().expect("full queue without a shadow");
+            let mut old = core::mem::replace(&mut self.current, shadow);
+            // SAFETY: every element was moved to the shadow, so all the
+            // slots are `None` and there is nothing to drop.
+            unsafe { old.buff.set_len(0) };
+        }
+        self.current.push_back(Some(element));
+        if self.shadow.is_none() && self.current.size >= self.current.capacity() / 2 {
+            self.shadow = Some(Ring::with_capacity(2 * self.current.capacity()));
+        }
+        if let Some(shadow) = self.shadow.as_mut() {
+            for _ in 0..2 {
+                if shadow.size == self.current.size {
+                    break;
+                }
+                let slot = self.current.slot(shadow.size);
+                shadow.push_back(self.current.buff[slot].take());
+            }
+        }
+    }
+
+    pub fn dequeue(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let element = match self.current.pop_front() {
+            Some(element) => element,
+            None => self
+                .shadow
+                .as_mut()
+                .and_then(Ring::pop_front)
+                .expect("copied element missing from the shadow"),
+        };
+        let copied = self.shadow.as_ref().map_or(0, |shadow| shadow.size);
+        if copied == 0 && self.current.size < self.current.capacity() / 2 {
+            self.shadow = None;
+        }
+        Some(element)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        match self.shadow.as_ref() {
+            Some(shadow) if shadow.size > 0 => shadow.front(),
+            _ => self.current.front(),
+        }
+    }
+}
+
+impl<T> Default for ShadowQueue<T> {
+    fn default() -> Self {
+        ShadowQueue::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::XorShift;
+
+    #[test]
+    fn test_enqueue_and_dequeue_element() {
+        let mut q: ShadowQueue<i32> = ShadowQueue::new();
+        assert_eq!(q.dequeue(), None);
+        q.enqueue(2);
+        q.enqueue(20);
+        q.enqueue(2000);
+        assert_eq!(q.peek(), Some(&2));
+        assert_eq!(q.dequeue(), Some(2));
+        assert_eq!(q.dequeue(), Some(20));
+        assert_eq!(q.dequeue(), Some(2000));
+        assert_eq!(q.dequeue(), None);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_grows_through_shadow() {
+        let mut q: ShadowQueue<String> = ShadowQueue::new();
+        for i in 0..10_000 {
+            q.enqueue(i.to_string());
+            assert_eq!(q.peek(), Some(&0.to_string()));
+        }
+        assert_eq!(q.len(), 10_000);
+        assert!(q.capacity() >= 10_000);
+        for i in 0..10_000 {
+            assert_eq!(q.peek(), Some(&i.to_string()));
+            assert_eq!(q.dequeue(), Some(i.to_string()));
+        }
+        assert_eq!(q.dequeue(), None);
+    }
+
+    #[test]
+    fn test_interleaved_operations_match_vec_deque() {
+        let mut q: ShadowQueue<u32> = ShadowQueue::new();
+        let mut expected = std::collections::VecDeque::new();
+        let mut rng = XorShift::new(7);
+        for i in 0..20_000 {
+            if rng.below(3) == 0 {
+                assert_eq!(q.dequeue(), expected.pop_front());
+            } else {
+                q.enqueue(i);
+                expected.push_back(i);
+            }
+            assert_eq!(q.peek(), expected.front());
+            assert_eq!(q.len(), expected.len());
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use alloc_crate::vec::Vec;
+
+/// Array based stack that never reallocates in a single push (Brass 1.5).
+///
+/// Once `current` is half full, a `shadow` buffer of twice its capacity
+/// is allocated, and every following push also moves the two lowest
+/// elements not yet copied into it. By the time `current` is full, the
+/// shadow holds every element and simply takes its place. Slots of
+/// `current` whose element was moved to the shadow are left as `None`;
+/// they always form a prefix of `current`, of length `shadow.len()`.
+pub struct ShadowStack<T> {
+    current: Vec<Option<T>>,
+    shadow: Option<Vec<Option<T>>>,
+}
+
+const MIN_CAPACITY: usize = 4;
+
+impl<T> ShadowStack<T> {
+    pub fn new() -> Self {
+        ShadowStack::with_capacity(MIN_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        ShadowStack {
+            current: Vec::with_capacity(capacity.max(MIN_CAPACITY)),
+            shadow: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.current.capacity()
+    }
+
+    pub fn push(&mut self, element: T) {
+        if self.current.len() == self.current.capacity() {
+            let shadow = self.shadow.take().expect("full stack without a shadow");
+            let mut old = core::mem::replace(&mut self.current, shadow);
+            // SAFETY: every element was moved to the shadow, so all the
+            // slots are `None` and there is nothing to drop.
+            unsafe { old.set_len(0) };
+        }
+        self.current.push(Some(element));
+        if self.shadow.is_none() && self.current.len() >= self.current.capacity() / 2 {
+            self.shadow = Some(Vec::with_capacity(2 * self.current.capacity()));
+        }
+        if let Some(shadow) = self.shadow.as_mut() {
+            for _ in 0..2 {
+                if shadow.len() == self.current.len() {
+                    break;
+                }
+                shadow.push(self.current[shadow.len()].take());
+            }
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let element = match self.current.pop()? {
+            Some(element) => element,
+            None => self
+                .shadow
+                .as_mut()
+                .and_then(Vec::pop)
+                .flatten()
+                .expect("copied element missing from the shadow"),
+        };
+        let copied = self.shadow.as_ref().map_or(0, Vec::len);
+        if copied == 0 && self.current.len() < self.current.capacity() / 2 {
+            self.shadow = None;
+        }
+        Some(element)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        match self.current.last()? {
+            Some(element) => Some(element),
+            None => self.shadow.as_ref()?.last()?.as_ref(),
+        }
+    }
+}
+
+impl<T> Default for ShadowStack<T> {
+    fn default() -> Self {
+        ShadowStack::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::XorShift;
+
+    #[test]
+    fn test_push_pop() {
+        let mut s: ShadowStack<i32> = ShadowStack::new();
+        assert_eq!(s.pop(), None);
+        s.push(12);
+        s.push(13);
+        assert_eq!(s.peek(), Some(&13));
+        assert_eq!(s.pop(), Some(13));
+        assert_eq!(s.pop(), Some(12));
+        assert_eq!(s.pop(), None);
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn test_grows_through_shadow() {
+        let mut s: ShadowStack<String> = ShadowStack::new();
+        for i in 0..10_000 {
+            s.push(i.to_string());
+            assert_eq!(s.peek(), Some(&i.to_string()));
+        }
+        assert_eq!(s.len(), 10_000);
+        assert!(s.capacity() >= 10_000);
+        for i in (0..10_000).rev() {
+            assert_eq!(s.peek(), Some(&i.to_string()));
+            assert_eq!(s.pop(), Some(i.to_string()));
+        }
+        assert_eq!(s.pop(), None);
+    }
+
+    #[test]
+    fn test_interleaved_operations_match_vec() {
+        let mut s: ShadowStack<u32> = ShadowStack::new();
+        let mut expected = Vec::new();
+        let mut rng = XorShift::new(7);
+        for i in 0..20_000 {
+            if rng.below(3) == 0 {
+                assert_eq!(s.pop(), expected.pop());
+            } else {
+                s.push(i);
+                expected.push(i);
+            }
+            assert_eq!(s.peek(), expected.last());
+            assert_eq!(s.len(), expected.len());
+        }
+    }
+}
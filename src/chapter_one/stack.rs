@@ -1,51 +1,294 @@
-use std::ops::DerefMut;
+use crate::alloc::{Handle, NodePool};
+use core::iter::FromIterator;
+use core::marker::PhantomData;
 
-#[allow(dead_code)]
-struct Stack<T> {
-    head: Option<Node<T>>,
+/// Linked stack: every push links a new node in front of `head`, and
+/// every pop moves the element out of the unlinked node.
+///
+/// The nodes live in a `NodePool`, so popped nodes are recycled by the
+/// next pushes and memory is requested a block at a time. The blocks
+/// are kept as the stack gets smaller, so it holds on to the memory of
+/// its largest size until `shrink_to_fit` is called.
+pub struct Stack<T> {
+    pool: NodePool<Node<T>>,
+    head: Link<T>,
+    size: usize,
 }
 
-#[derive(Clone)]
+type Link<T> = Option<Handle<Node<T>>>;
+
 struct Node<T> {
-    element: Option<T>,
-    next: Option<Box<Node<T>>>,
-}
-
-impl<T> Stack<T>
-where
-    T: Clone,
-{
-    #[allow(dead_code)]
-    fn new(head: Option<Node<T>>) -> Self {
-        Stack { head }
-    }
-
-    #[allow(dead_code)]
-    fn pop(&mut self) -> Option<T> {
-        match &mut self.head {
-            None => None,
-            Some(node) => {
-                let element = node.element.as_ref().cloned();
-                self.head = match &mut node.next {
-                    None => None,
-                    Some(next) => Some(next.deref_mut().clone()),
-                };
-                element
-            }
+    element: T,
+    next: Link<T>,
+}
+
+impl<T> Stack<T> {
+    pub fn new() -> Self {
+        Stack {
+            pool: NodePool::new(),
+            head: None,
+            size: 0,
         }
     }
-    #[allow(dead_code)]
-    fn push(&mut self, element: T) {
-        let mut node = Node {
-            element: Some(element),
-            next: None,
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let Node { element, next } = self.pool.free(self.head?);
+        self.head = next;
+        self.size -= 1;
+        Some(element)
+    }
+
+    pub fn push(&mut self, element: T) {
+        let node = Node {
+            element,
+            next: self.head.take(),
         };
-        if self.head.is_none() {
-            self.head = Some(node);
-        } else {
-            let temp = self.head.as_ref().cloned();
-            node.next = temp.map(Box::new);
+        self.head = Some(self.pool.alloc(node));
+        self.size += 1;
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        Some(&self.pool.get(self.head?).element)
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        Some(&mut self.pool.get_mut(self.head?).element)
+    }
+
+    /// Keeps the `len` elements on top and drops the ones below them.
+    /// Takes O(`len`) to find the cut, plus the elements dropped.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.size {
+            return;
         }
+        let mut rest = match len {
+            0 => self.head.take(),
+            _ => {
+                let mut last = self.head.expect("stack holds more than len");
+                for _ in 1..len {
+                    last = self.pool.get(last).next.expect("stack holds more than len");
+                }
+                self.pool.get_mut(last).next.take()
+            }
+        };
+        while let Some(handle) = rest {
+            rest = self.pool.free(handle).next;
+        }
+        self.size = len;
+    }
+
+    /// Releases the blocks no longer needed for the elements left.
+    pub fn shrink_to_fit(&mut self) {
+        self.pool.shrink();
+    }
+}
+
+impl<T> Stack<T> {
+    /// Iterates from the top of the stack to the bottom.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            pool: &self.pool,
+            next: self.head,
+            len: self.size,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            pool: &mut self.pool,
+            next: self.head,
+            len: self.size,
+            marker: PhantomData,
+        }
+    }
+
+    /// Pops every element, top first. Elements not consumed by the
+    /// iterator are dropped along with it.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain { stack: self }
+    }
+}
+
+pub struct Iter<'a, T> {
+    pool: &'a NodePool<Node<T>>,
+    next: Link<T>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.pool.get(self.next?);
+        self.next = node.next;
+        self.len -= 1;
+        Some(&node.element)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+pub struct IterMut<'a, T> {
+    pool: &'a mut NodePool<Node<T>>,
+    next: Link<T>,
+    len: usize,
+    marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // SAFETY: the links never visit a node twice, so each node is
+        // handed out once, and `get_ptr` leaves the nodes handed out
+        // before untouched. The pool is borrowed for `'a`, so no node is
+        // freed in the meantime.
+        let node = unsafe { &mut *self.pool.get_ptr(self.next?) };
+        self.next = node.next;
+        self.len -= 1;
+        Some(&mut node.element)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+
+pub struct IntoIter<T>(Stack<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len(), Some(self.0.len()))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+pub struct Drain<'a, T> {
+    stack: &'a mut Stack<T>,
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.stack.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.stack.len(), Some(self.stack.len()))
+    }
+}
+
+impl<T> ExactSizeIterator for Drain<'_, T> {}
+
+impl<T> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        for _ in self {}
+    }
+}
+
+impl<T> IntoIterator for Stack<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Stack<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut Stack<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+/// Pushes the elements in order, so the last one ends up on top.
+impl<T> FromIterator<T> for Stack<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut stack = Stack::new();
+        stack.extend(iter);
+        stack
+    }
+}
+
+impl<T> Extend<T> for Stack<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for element in iter {
+            self.push(element);
+        }
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for Stack<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Clone> Clone for Stack<T> {
+    fn clone(&self) -> Self {
+        let mut stack = Stack::new();
+        let mut tail: Link<T> = None;
+        for element in self.iter() {
+            let node = stack.pool.alloc(Node {
+                element: element.clone(),
+                next: None,
+            });
+            match tail {
+                None => stack.head = Some(node),
+                Some(tail) => stack.pool.get_mut(tail).next = Some(node),
+            }
+            tail = Some(node);
+        }
+        stack.size = self.size;
+        stack
+    }
+}
+
+impl<T: PartialEq> PartialEq for Stack<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for Stack<T> {}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Stack::new()
     }
 }
 
@@ -55,21 +298,15 @@ mod test {
 
     #[test]
     fn test_pop_empty_stack() {
-        let mut s: Stack<i32> = Stack::new(None);
+        let mut s: Stack<i32> = Stack::new();
         assert_eq!(s.pop(), None);
     }
 
     #[test]
     fn test_pop_non_empty_stack() {
-        let node = Node {
-            element: Some(3),
-            next: None,
-        };
-        let node2 = Node {
-            element: Some(2),
-            next: Some(Box::new(node)),
-        };
-        let mut s: Stack<i32> = Stack::new(Some(node2));
+        let mut s: Stack<i32> = Stack::new();
+        s.push(3);
+        s.push(2);
         assert_eq!(s.pop(), Some(2));
         assert_eq!(s.pop(), Some(3));
         assert_eq!(s.pop(), None);
@@ -77,9 +314,105 @@ mod test {
 
     #[test]
     fn test_push_pop() {
-        let mut s: Stack<i32> = Stack::new(None);
+        let mut s: Stack<i32> = Stack::new();
         s.push(12);
         assert_eq!(s.pop(), Some(12));
         assert_eq!(s.pop(), None);
     }
+
+    #[test]
+    fn test_peek_and_len() {
+        let mut s: Stack<String> = Stack::new();
+        assert_eq!(s.peek(), None);
+        s.push("a".to_string());
+        s.push("b".to_string());
+        assert_eq!(s.len(), 2);
+        assert_eq!(s.peek().map(String::as_str), Some("b"));
+        if let Some(top) = s.peek_mut() {
+            top.push('c');
+        }
+        assert_eq!(s.pop(), Some("bc".to_string()));
+        assert_eq!(s.len(), 1);
+        assert!(!s.is_empty());
+    }
+
+    #[test]
+    fn test_drop_long_stack() {
+        let mut s: Stack<u64> = Stack::new();
+        for i in 0..1_000_000 {
+            s.push(i);
+        }
+        assert_eq!(s.len(), 1_000_000);
+    }
+
+    #[test]
+    fn test_iterators() {
+        let mut s: Stack<i32> = (1..=4).collect();
+        assert_eq!(s.iter().collect::<Vec<_>>(), [&4, &3, &2, &1]);
+        for element in &mut s {
+            *element *= 10;
+        }
+        assert_eq!((&s).into_iter().len(), 4);
+        s.extend(vec![50]);
+        assert_eq!(s.into_iter().collect::<Vec<_>>(), [50, 40, 30, 20, 10]);
+    }
+
+    #[test]
+    fn test_clone_eq_debug_default() {
+        let s: Stack<String> = ["a", "b"].iter().map(|e| e.to_string()).collect();
+        let copy = s.clone();
+        assert_eq!(copy, s);
+        assert_eq!(format!("{:?}", copy), r#"["b", "a"]"#);
+        assert_ne!(copy, Stack::default());
+        assert_eq!(copy.len(), 2);
+    }
+
+    #[test]
+    fn test_recycles_popped_nodes() {
+        let mut s: Stack<u32> = (0..100).collect();
+        let blocks = s.pool.stats().blocks;
+        for round in 0..10 {
+            for _ in 0..50 {
+                s.pop();
+            }
+            s.extend(round..round + 50);
+        }
+        assert_eq!(s.pool.stats().blocks, blocks);
+        assert_eq!(s.pool.stats().nodes_in_use, 100);
+    }
+
+    #[test]
+    fn test_shrink_to_fit_releases_blocks() {
+        let mut s: Stack<u32> = (0..1_000).collect();
+        for _ in 0..990 {
+            s.pop();
+        }
+        s.shrink_to_fit();
+        assert_eq!(s.pool.stats().blocks, 1);
+        assert_eq!(
+            s.iter().copied().collect::<Vec<_>>(),
+            (0..10).rev().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut s: Stack<i32> = (0..10).collect();
+        s.truncate(20);
+        assert_eq!(s.len(), 10);
+        s.truncate(3);
+        assert_eq!(s.iter().collect::<Vec<_>>(), [&9, &8, &7]);
+        assert_eq!(s.pool.len(), 3);
+        s.truncate(0);
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut s: Stack<i32> = (0..10).collect();
+        assert_eq!(s.drain().take(3).collect::<Vec<_>>(), [9, 8, 7]);
+        assert!(s.is_empty());
+        s.push(1);
+        assert_eq!(s.pop(), Some(1));
+    }
 }
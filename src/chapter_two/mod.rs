@@ -1 +1,2 @@
+pub mod multi_map;
 pub mod search_tree;
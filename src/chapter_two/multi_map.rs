@@ -0,0 +1,231 @@
+use super::search_tree::{self, BinarySearchTree};
+use crate::chapter_one::queue::{self, Queue};
+
+/// Search tree that keeps every value inserted under a key.
+///
+/// Brass (2.5) lists two ways to allow non-unique keys: store equal
+/// keys as separate nodes, or store a single node per key with a list
+/// of its values. This is the second one; the values of a key sit in a
+/// `Queue`, so they come out in the order they were inserted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BinarySearchMultiMap<K, V> {
+    tree: BinarySearchTree<K, Queue<V>>,
+    len: usize,
+}
+
+impl<K: Ord, V> BinarySearchMultiMap<K, V> {
+    pub fn new() -> Self {
+        BinarySearchMultiMap {
+            tree: BinarySearchTree::new(),
+            len: 0,
+        }
+    }
+
+    /// Number of values, counting every value of a key.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds `value` after the values already under `key`.
+    pub fn insert(&mut self, key: K, value: V) {
+        match self.tree.get_mut(&key) {
+            Some(values) => values.enqueue(value),
+            None => {
+                let mut values = Queue::new();
+                values.enqueue(value);
+                self.tree.insert(key, values);
+            }
+        }
+        self.len += 1;
+    }
+
+    /// The first value inserted under `key`.
+    pub fn find(&self, key: &K) -> Option<&V> {
+        self.tree.get(key)?.peek()
+    }
+
+    /// The values under `key`, in insertion order.
+    pub fn find_all(&self, key: &K) -> Values<'_, V> {
+        Values(self.tree.get(key).map(Queue::iter))
+    }
+
+    pub fn count(&self, key: &K) -> usize {
+        self.find_all(key).len()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Removes and returns the first value inserted under `key`; the key
+    /// goes with its last value.
+    pub fn remove_one(&mut self, key: &K) -> Option<V> {
+        let values = self.tree.get_mut(key)?;
+        let value = values.dequeue();
+        if values.is_empty() {
+            self.tree.delete(key);
+        }
+        self.len -= 1;
+        value
+    }
+
+    /// Removes `key` and returns its values in insertion order, which
+    /// is none if the key was absent.
+    pub fn remove_all(&mut self, key: &K) -> Queue<V> {
+        let values = match self.tree.get_mut(key) {
+            Some(values) => core::mem::take(values),
+            None => return Queue::new(),
+        };
+        self.tree.delete(key);
+        self.len -= values.len();
+        values
+    }
+}
+
+impl<K, V> BinarySearchMultiMap<K, V> {
+    /// Iterates in key order, and in insertion order among the values
+    /// of a key.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            keys: self.tree.iter(),
+            current: None,
+        }
+    }
+}
+
+impl<K, V> Default for BinarySearchMultiMap<K, V> {
+    fn default() -> Self {
+        BinarySearchMultiMap {
+            tree: BinarySearchTree::default(),
+            len: 0,
+        }
+    }
+}
+
+pub struct Values<'a, V>(Option<queue::Iter<'a, V>>);
+
+impl<'a, V> Iterator for Values<'a, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.0.as_mut()?.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.as_ref().map_or((0, Some(0)), Iterator::size_hint)
+    }
+}
+
+impl<V> ExactSizeIterator for Values<'_, V> {}
+
+pub struct Iter<'a, K, V> {
+    keys: search_tree::Iter<'a, K, Queue<V>>,
+    current: Option<(&'a K, queue::Iter<'a, V>)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, values)) = self.current.as_mut() {
+                if let Some(value) = values.next() {
+                    return Some((*key, value));
+                }
+            }
+            let (key, values) = self.keys.next()?;
+            self.current = Some((key, values.iter()));
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a BinarySearchMultiMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::XorShift;
+    use std::collections::{BTreeMap, VecDeque};
+
+    #[test]
+    fn test_values_keep_insertion_order() {
+        let mut map = BinarySearchMultiMap::new();
+        for (key, value) in [(2, "b1"), (1, "a1"), (2, "b2"), (3, "c1"), (2, "b3")].iter() {
+            map.insert(*key, *value);
+        }
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.find(&2), Some(&"b1"));
+        assert_eq!(map.find_all(&2).collect::<Vec<_>>(), [&"b1", &"b2", &"b3"]);
+        assert_eq!(map.count(&4), 0);
+        assert_eq!(map.find_all(&4).next(), None);
+        let entries: Vec<_> = map.iter().map(|(key, value)| (*key, *value)).collect();
+        assert_eq!(
+            entries,
+            [(1, "a1"), (2, "b1"), (2, "b2"), (2, "b3"), (3, "c1")]
+        );
+    }
+
+    #[test]
+    fn test_remove_one_and_all() {
+        let mut map = BinarySearchMultiMap::new();
+        for value in 0..4 {
+            map.insert("k", value);
+        }
+        map.insert("j", 10);
+        assert_eq!(map.remove_one(&"k"), Some(0));
+        assert_eq!(map.remove_one(&"k"), Some(1));
+        assert_eq!(map.remove_all(&"k").into_iter().collect::<Vec<_>>(), [2, 3]);
+        assert!(!map.contains_key(&"k"));
+        assert_eq!(map.remove_one(&"k"), None);
+        assert!(map.remove_all(&"k").is_empty());
+        assert_eq!(map.remove_one(&"j"), Some(10));
+        assert!(!map.contains_key(&"j"));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_matches_btree_map_of_queues() {
+        let mut rng = XorShift::new(0x9e37_79b9_7f4a_7c15);
+        let mut map = BinarySearchMultiMap::new();
+        let mut model: BTreeMap<u64, VecDeque<u64>> = BTreeMap::new();
+        for i in 0..5_000 {
+            let key = rng.below(50);
+            match rng.below(10) {
+                0 => {
+                    let removed: Vec<_> = map.remove_all(&key).into_iter().collect();
+                    let expected = model.remove(&key).unwrap_or_default();
+                    assert_eq!(removed, Vec::from(expected));
+                }
+                1..=3 => {
+                    let expected = model.get_mut(&key).and_then(VecDeque::pop_front);
+                    if model.get(&key).is_some_and(VecDeque::is_empty) {
+                        model.remove(&key);
+                    }
+                    assert_eq!(map.remove_one(&key), expected);
+                }
+                _ => {
+                    map.insert(key, i);
+                    model.entry(key).or_default().push_back(i);
+                }
+            }
+            assert_eq!(map.len(), model.values().map(VecDeque::len).sum());
+        }
+        let entries: Vec<_> = map.iter().map(|(key, value)| (*key, *value)).collect();
+        let expected: Vec<_> = model
+            .iter()
+            .flat_map(|(key, values)| values.iter().map(move |value| (*key, *value)))
+            .collect();
+        assert_eq!(entries, expected);
+    }
+}
@@ -1,488 +1,1130 @@
-use std::boxed::Box;
-use std::cmp::Ordering;
+use crate::alloc::{Handle, NodePool};
+use crate::chapter_one::deque::ArrayDeque;
+use crate::chapter_one::queue::Queue;
+use crate::chapter_one::stack::Stack;
+use core::cmp::Ordering;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
 
-#[derive(Clone, Debug, PartialEq)]
-struct BinarySearchTree<K, V> {
-    root: Option<Edge<K, V>>,
+/// Unbalanced binary search tree.
+///
+/// The nodes live in a `NodePool` owned by the tree and link to their
+/// children by handle, so deleted nodes are recycled by later inserts.
+/// Dropping the tree drops the pool, which frees the nodes block by
+/// block rather than by walking down the tree.
+#[derive(Clone)]
+pub struct BinarySearchTree<K, V> {
+    pool: NodePool<Node<K, V>>,
+    root: Link<K, V>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+type Link<K, V> = Option<Handle<Node<K, V>>>;
+
+#[derive(Clone)]
 struct Node<K, V> {
     key: K,
     value: V,
-    left: Edge<K, V>,
-    right: Edge<K, V>,
+    left: Link<K, V>,
+    right: Link<K, V>,
+}
+
+impl<K, V> From<(K, V)> for Node<K, V> {
+    fn from(key_val: (K, V)) -> Self {
+        Node {
+            key: key_val.0,
+            value: key_val.1,
+            left: None,
+            right: None,
+        }
+    }
 }
 
-#[derive(Clone, Debug, PartialEq)]
-struct Edge<K, V> {
-    node: Option<Box<Node<K, V>>>,
+/// Where a link is stored: at the root, or as a child of a node. Walks
+/// that relink subtrees keep a place instead of a reference to the link,
+/// which would borrow the whole pool.
+enum Place<K, V> {
+    Root,
+    Left(Handle<Node<K, V>>),
+    Right(Handle<Node<K, V>>),
 }
 
-impl<K, V> From<Node<K, V>> for Edge<K, V> {
-    fn from(node: Node<K, V>) -> Self {
-        Edge {
-            node: Some(Box::new(node)),
+impl<K, V> Clone for Place<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for Place<K, V> {}
+
+impl<K: Ord, V> BinarySearchTree<K, V> {
+    pub fn new() -> BinarySearchTree<K, V> {
+        BinarySearchTree {
+            pool: NodePool::new(),
+            root: None,
+        }
+    }
+
+    /// Like `get`, taking the key by value.
+    pub fn find(&self, key_to_find: K) -> Option<&V> {
+        self.get(&key_to_find)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let handle = self.link(self.place_of(key))?;
+        Some(&self.pool.get(handle).value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let handle = self.link(self.place_of(key))?;
+        Some(&mut self.pool.get_mut(handle).value)
+    }
+
+    pub fn insert(&mut self, key_to_insert: K, value_to_insert: V) {
+        let place = self.place_of(&key_to_insert);
+        match self.link(place) {
+            Some(handle) => self.pool.get_mut(handle).value = value_to_insert,
+            None => {
+                let handle = self
+                    .pool
+                    .alloc(Node::from((key_to_insert, value_to_insert)));
+                self.set_link(place, Some(handle));
+            }
+        }
+    }
+
+    /// Removes the entry with the given key and returns it. A node with
+    /// two children is replaced by its in-order successor, the leftmost
+    /// node of its right subtree.
+    pub fn delete(&mut self, key_to_delete: &K) -> Option<(K, V)> {
+        let node = self.unlink(self.place_of(key_to_delete))?;
+        Some((node.key, node.value))
+    }
+
+    /// Panics unless the keys increase strictly from left to right, or
+    /// if the pool holds nodes the tree cannot reach. An in-order walk
+    /// checks every node against both its subtrees.
+    pub fn check_invariants(&self) {
+        let mut reached = 0;
+        let mut keys = self.iter().map(|(key, _)| key).inspect(|_| reached += 1);
+        if let Some(mut previous) = keys.next() {
+            for key in keys {
+                assert!(previous < key, "keys out of order");
+                previous = key;
+            }
+        }
+        assert_eq!(reached, self.pool.len(), "unreachable nodes in the pool");
+    }
+
+    /// The place holding the node with `key`, or the empty place where
+    /// it belongs.
+    fn place_of(&self, key: &K) -> Place<K, V> {
+        let mut place = Place::Root;
+        while let Some(handle) = self.link(place) {
+            place = match key.cmp(&self.pool.get(handle).key) {
+                Ordering::Equal => break,
+                Ordering::Less => Place::Left(handle),
+                Ordering::Greater => Place::Right(handle),
+            };
+        }
+        place
+    }
+
+    /// Entry with the smallest key: the leftmost node.
+    pub fn min(&self) -> Option<(&K, &V)> {
+        let mut node = self.pool.get(self.root?);
+        while let Some(left) = node.left {
+            node = self.pool.get(left);
+        }
+        Some((&node.key, &node.value))
+    }
+
+    /// Entry with the largest key: the rightmost node.
+    pub fn max(&self) -> Option<(&K, &V)> {
+        let mut node = self.pool.get(self.root?);
+        while let Some(right) = node.right {
+            node = self.pool.get(right);
         }
+        Some((&node.key, &node.value))
     }
 }
 
-impl<K, V> From<(K, V)> for Node<K, V> {
-    fn from(key_val: (K, V)) -> Self {
-        Node {
-            key: key_val.0,
-            value: key_val.1,
-            left: Edge { node: None },
-            right: Edge { node: None },
+impl<K, V> Default for BinarySearchTree<K, V> {
+    fn default() -> Self {
+        BinarySearchTree {
+            pool: NodePool::new(),
+            root: None,
         }
     }
 }
 
-impl<K, V> From<(K, V)> for Edge<K, V> {
-    fn from(key_val: (K, V)) -> Self {
-        Edge {
-            node: Some(Box::new(Node {
-                key: key_val.0,
-                value: key_val.1,
-                left: Edge { node: None },
-                right: Edge { node: None },
-            })),
-        }
-    }
-}
-
-impl<K, V> BinarySearchTree<K, V>
-where
-    K: Clone + PartialOrd + Eq + Ord + std::fmt::Debug,
-    V: Clone + PartialOrd + Eq + std::fmt::Debug,
-{
-    #[allow(dead_code)]
-    fn new(maybe_node: Option<Node<K, V>>) -> BinarySearchTree<K, V> {
-        let root = match maybe_node {
-            None => None,
-            Some(node) => Some(Edge::from(node)),
+/// Trees are equal when they have the same entries in the same shape.
+/// The keys in pre-order fix the shape of a search tree, so comparing
+/// the pre-order entries is enough; where the nodes sit in the pools
+/// does not matter.
+impl<K: PartialEq, V: PartialEq> PartialEq for BinarySearchTree<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.pool.len() == other.pool.len() && self.pre_order().eq(other.pre_order())
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for BinarySearchTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Links, and the relinking shared by deletion and range removal.
+impl<K, V> BinarySearchTree<K, V> {
+    fn link(&self, place: Place<K, V>) -> Link<K, V> {
+        match place {
+            Place::Root => self.root,
+            Place::Left(parent) => self.pool.get(parent).left,
+            Place::Right(parent) => self.pool.get(parent).right,
+        }
+    }
+
+    fn set_link(&mut self, place: Place<K, V>, link: Link<K, V>) {
+        match place {
+            Place::Root => self.root = link,
+            Place::Left(parent) => self.pool.get_mut(parent).left = link,
+            Place::Right(parent) => self.pool.get_mut(parent).right = link,
+        }
+    }
+
+    /// Frees the node at `place` and puts its in-order successor in its
+    /// place.
+    fn unlink(&mut self, place: Place<K, V>) -> Option<Node<K, V>> {
+        let handle = self.link(place)?;
+        let successor = self.take_min(Place::Right(handle));
+        let node = self.pool.free(handle);
+        let replacement = match successor {
+            None => node.left,
+            Some(successor) => {
+                let moved = self.pool.get_mut(successor);
+                moved.left = node.left;
+                moved.right = node.right;
+                Some(successor)
+            }
         };
-        BinarySearchTree { root }
+        self.set_link(place, replacement);
+        Some(node)
     }
 
-    #[allow(dead_code)]
-    fn find(&self, key_to_find: K) -> Option<&V> {
-        match &self.root {
-            None => None,
-            Some(edge) => edge.find(key_to_find),
+    /// Detaches the leftmost node of the subtree at `place`, leaving its
+    /// right subtree in its place.
+    fn take_min(&mut self, mut place: Place<K, V>) -> Link<K, V> {
+        let mut handle = self.link(place)?;
+        while let Some(left) = self.pool.get(handle).left {
+            place = Place::Left(handle);
+            handle = left;
         }
+        let right = self.pool.get(handle).right;
+        self.set_link(place, right);
+        Some(handle)
     }
 
-    #[allow(dead_code)]
-    fn insert(&mut self, key_to_insert: K, value_to_insert: V) {
-        match self.root.as_mut() {
-            // There no root
-            None => self.root = Some(Edge::from((key_to_insert, value_to_insert))),
-            // There is a root
-            Some(edge) => match edge {
-                Edge { node: None } => {
-                    edge.node = Some(Box::new(Node::from((key_to_insert, value_to_insert))));
+    /// Frees the subtree one node at a time, however deep it is, and
+    /// returns how many nodes it had.
+    fn clear(&mut self, link: Link<K, V>) -> usize {
+        let mut count = 0;
+        let mut pending = Stack::new();
+        pending.extend(link);
+        while let Some(handle) = pending.pop() {
+            let node = self.pool.free(handle);
+            count += 1;
+            pending.extend(node.left);
+            pending.extend(node.right);
+        }
+        count
+    }
+
+    /// Releases the pool blocks left empty by deletions.
+    pub fn shrink_to_fit(&mut self) {
+        self.pool.shrink();
+    }
+}
+
+/// Traversals. They keep their own stack (or queue) of pending nodes
+/// instead of recursing, and only visit a node when asked for it.
+impl<K, V> BinarySearchTree<K, V> {
+    /// Iterates in key order; it can also be run from the largest key.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            pool: &self.pool,
+            pending: whole_tree(self.root),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            pending: whole_tree(self.root),
+            pool: &mut self.pool,
+            marker: PhantomData,
+        }
+    }
+
+    /// Visits every node before its left subtree, and that before its
+    /// right subtree.
+    pub fn pre_order(&self) -> PreOrder<'_, K, V> {
+        PreOrder {
+            pool: &self.pool,
+            pending: self.root.into_iter().collect(),
+        }
+    }
+
+    /// Visits every node after its left and right subtrees.
+    pub fn post_order(&self) -> PostOrder<'_, K, V> {
+        PostOrder {
+            pool: &self.pool,
+            pending: self.root.map(|root| (root, false)).into_iter().collect(),
+        }
+    }
+
+    /// Visits the nodes by depth, and from left to right at each depth.
+    pub fn level_order(&self) -> LevelOrder<'_, K, V> {
+        LevelOrder {
+            pool: &self.pool,
+            pending: self.root.into_iter().collect(),
+        }
+    }
+
+    pub fn into_pre_order(self) -> IntoPreOrder<K, V> {
+        IntoPreOrder {
+            pending: self.root.into_iter().collect(),
+            pool: self.pool,
+        }
+    }
+
+    pub fn into_post_order(self) -> IntoPostOrder<K, V> {
+        IntoPostOrder {
+            pending: self.root.map(|root| (root, false)).into_iter().collect(),
+            pool: self.pool,
+        }
+    }
+
+    pub fn into_level_order(self) -> IntoLevelOrder<K, V> {
+        IntoLevelOrder {
+            pending: self.root.into_iter().collect(),
+            pool: self.pool,
+        }
+    }
+}
+
+/// Range queries. On a tree of height h they take O(h) plus the
+/// number of entries in the range.
+impl<K: Ord, V> BinarySearchTree<K, V> {
+    /// In-order iterator over the entries with keys in `range`. The
+    /// paths to both ends of the range are split up front, so the
+    /// iterator never compares keys and only sees subtrees that lie
+    /// entirely in the range.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, V> {
+        Iter {
+            pool: &self.pool,
+            pending: self.split_range(&range),
+        }
+    }
+
+    /// Like `range`, with mutable access to the values.
+    pub fn range_mut<R: RangeBounds<K>>(&mut self, range: R) -> IterMut<'_, K, V> {
+        IterMut {
+            pending: self.split_range(&range),
+            pool: &mut self.pool,
+            marker: PhantomData,
+        }
+    }
+
+    /// The in-order sequence of `range` as subtrees and single nodes:
+    /// the topmost node in the range, and along the paths from it to
+    /// both ends of the range, the nodes in the range with their inner
+    /// subtrees.
+    fn split_range<R: RangeBounds<K>>(&self, range: &R) -> ArrayDeque<Pending<K, V>> {
+        let mut pending = ArrayDeque::new();
+        let mut link = self.root;
+        while let Some(split) = link {
+            let node = self.pool.get(split);
+            if below(&node.key, range) {
+                link = node.right;
+            } else if above(&node.key, range) {
+                link = node.left;
+            } else {
+                pending.push_back(Pending::Entry(split));
+                let mut lower = node.left;
+                while let Some(handle) = lower {
+                    let node = self.pool.get(handle);
+                    if below(&node.key, range) {
+                        lower = node.right;
+                    } else {
+                        if let Some(child) = node.right {
+                            pending.push_front(Pending::Tree(child));
+                        }
+                        pending.push_front(Pending::Entry(handle));
+                        lower = node.left;
+                    }
                 }
-                Edge { node: Some(_) } => edge.insert(key_to_insert, value_to_insert),
-            },
-        }
-    }
-
-    #[allow(dead_code)]
-    fn delete(&mut self, key_to_delete: K) {
-        match self.root.as_mut() {
-            None => (),
-            Some(edge) => edge.delete(key_to_delete),
-        }
-    }
-}
-
-impl<K, V> Edge<K, V>
-where
-    K: Clone + PartialOrd + Eq + Ord + std::fmt::Debug,
-    V: Clone + PartialOrd + Eq + std::fmt::Debug,
-{
-    #[allow(dead_code)]
-    fn find(&self, key_to_find: K) -> Option<&V> {
-        match &self.node {
-            None => None,
-            Some(boxed_node) => match &**boxed_node {
-                Node {
-                    key,
-                    value,
-                    left,
-                    right,
-                } => match key_to_find.cmp(&key) {
-                    Ordering::Equal => Some(value),
-                    Ordering::Less => left.find(key_to_find),
-                    Ordering::Greater => right.find(key_to_find),
-                },
-            },
-        }
-    }
-    fn insert(&mut self, key_to_insert: K, value_to_insert: V) {
-        match self {
-            Edge { node } => match node {
-                None => *node = Some(Box::new(Node::from((key_to_insert, value_to_insert)))),
-                Some(boxed_node) => match &mut **boxed_node {
-                    Node {
-                        key, left, right, ..
-                    } => match key_to_insert.cmp(key) {
-                        Ordering::Equal => {
-                            *boxed_node = Box::new(Node {
-                                key: key.clone(),
-                                value: value_to_insert,
-                                left: left.clone(),
-                                right: right.clone(),
-                            });
+                let mut upper = node.right;
+                while let Some(handle) = upper {
+                    let node = self.pool.get(handle);
+                    if above(&node.key, range) {
+                        upper = node.left;
+                    } else {
+                        if let Some(child) = node.left {
+                            pending.push_back(Pending::Tree(child));
                         }
-                        Ordering::Greater => right.insert(key_to_insert, value_to_insert),
-                        Ordering::Less => left.insert(key_to_insert, value_to_insert),
-                    },
-                },
-            },
-        }
-    }
-
-    fn delete(&mut self, key_to_delete: K) {
-        match self {
-            Edge { node } => match node {
-                None => (),
-                Some(boxed_node) => match &mut **boxed_node {
-                    Node {
-                        key,
-                        left,
-                        right,
-                        value,
-                    } => match key_to_delete.cmp(key) {
-                        Ordering::Equal => match (&mut *left, &mut *right) {
-                            (Edge { node: None }, Edge { node: None }) => {
-                                *self = Edge { node: None }
-                            }
-                            (
-                                Edge {
-                                    node: Some(boxed_node_left),
-                                },
-                                Edge { node: None },
-                            ) => match &mut **boxed_node_left {
-                                Node {
-                                    key: key_left_only,
-                                    value: value_left_only,
-                                    left: left_left_only,
-                                    right: right_left_only,
-                                } => match (&mut *left_left_only, &mut *right_left_only) {
-                                    (Edge { node: None }, Edge { node: None }) => {
-                                        *key = key_left_only.clone();
-                                        *value = value_left_only.clone();
-                                        *left = Edge { node: None };
-                                    }
-                                    (
-                                        Edge {
-                                            node: Some(boxed_node_left),
-                                        },
-                                        Edge { node: None },
-                                    ) => {
-                                        *key = boxed_node_left.key.clone();
-                                        *value = boxed_node_left.value.clone();
-                                        let key_to_remove = boxed_node_left.key.clone();
-                                        left_left_only.delete(key_to_remove);
-                                    }
-                                    (
-                                        Edge { node: None },
-                                        Edge {
-                                            node: Some(boxed_node_right),
-                                        },
-                                    ) => {
-                                        *key = boxed_node_right.key.clone();
-                                        *value = boxed_node_right.value.clone();
-                                        let key_to_remove = boxed_node_right.key.clone();
-                                        right_left_only.delete(key_to_remove);
-                                    }
-                                    (
-                                        Edge {
-                                            node: Some(_boxed_node_left),
-                                        },
-                                        Edge {
-                                            node: Some(boxed_node_right),
-                                        },
-                                    ) => {
-                                        *key = boxed_node_right.key.clone();
-                                        *value = boxed_node_right.value.clone();
-                                        let key_to_remove = boxed_node_right.key.clone();
-                                        right_left_only.delete(key_to_remove);
-                                    }
-                                },
-                            },
-                            (
-                                Edge { node: None },
-                                Edge {
-                                    node: Some(boxed_node_right),
-                                },
-                            ) => match &mut **boxed_node_right {
-                                Node {
-                                    key: key_right_only,
-                                    value: value_right_only,
-                                    left: left_right_only,
-                                    right: right_right_only,
-                                } => match (&mut *left_right_only, &mut *right_right_only) {
-                                    (Edge { node: None }, Edge { node: None }) => {
-                                        *key = key_right_only.clone();
-                                        *value = value_right_only.clone();
-                                        *right = Edge { node: None };
-                                    }
-                                    (
-                                        Edge {
-                                            node: Some(boxed_node_left),
-                                        },
-                                        Edge { node: None },
-                                    ) => {
-                                        *key = key_right_only.clone();
-                                        *value = value_right_only.clone();
-                                        let key_to_remove = boxed_node_left.key.clone();
-                                        left_right_only.delete(key_to_remove);
-                                    }
-                                    (
-                                        Edge { node: None },
-                                        Edge {
-                                            node: Some(boxed_node_right),
-                                        },
-                                    ) => {
-                                        *key = key_right_only.clone();
-                                        *value = value_right_only.clone();
-                                        let key_to_remove = boxed_node_right.key.clone();
-                                        dbg!(&right_right_only);
-                                        dbg!(&key_to_remove);
-                                        right_right_only.delete(key_to_remove);
-                                    }
-                                    (
-                                        Edge {
-                                            node: Some(_boxed_node_left),
-                                        },
-                                        Edge {
-                                            node: Some(boxed_node_right),
-                                        },
-                                    ) => {
-                                        *key = key_right_only.clone();
-                                        *value = value_right_only.clone();
-                                        let key_to_remove = boxed_node_right.key.clone();
-                                        right_right_only.delete(key_to_remove);
-                                    }
-                                },
-                            },
-                            (
-                                Edge {
-                                    node: Some(boxed_node_left),
-                                },
-                                Edge {
-                                    node: Some(boxed_node_right),
-                                },
-                            ) => {
-                                if boxed_node_right.key == key_to_delete {
-                                    match &mut **boxed_node_right {
-                                        Node {
-                                            key: key_left_and_right,
-                                            value: value_left_and_right,
-                                            left: left_left_and_right,
-                                            right: right_left_and_right,
-                                        } => match (
-                                            &mut *left_left_and_right,
-                                            &mut *right_left_and_right,
-                                        ) {
-                                            (Edge { node: None }, Edge { node: None }) => {
-                                                *key = key_left_and_right.clone();
-                                                *value = value_left_and_right.clone();
-                                                *right = Edge { node: None };
-                                            }
-                                            (
-                                                Edge {
-                                                    node: Some(boxed_node_left),
-                                                },
-                                                Edge { node: None },
-                                            ) => {
-                                                *key = key_left_and_right.clone();
-                                                *value = value_left_and_right.clone();
-                                                let key_to_remove = boxed_node_left.key.clone();
-                                                left_left_and_right.delete(key_to_remove);
-                                            }
-                                            (
-                                                Edge { node: None },
-                                                Edge {
-                                                    node: Some(boxed_node_right),
-                                                },
-                                            ) => {
-                                                *key = key_left_and_right.clone();
-                                                *value = value_left_and_right.clone();
-                                                let key_to_remove = boxed_node_right.key.clone();
-                                                right_left_and_right.delete(key_to_remove);
-                                            }
-                                            (
-                                                Edge {
-                                                    node: Some(_boxed_node_left),
-                                                },
-                                                Edge {
-                                                    node: Some(boxed_node_right),
-                                                },
-                                            ) => {
-                                                *key = key_left_and_right.clone();
-                                                *value = value_left_and_right.clone();
-                                                let key_to_remove = boxed_node_right.key.clone();
-                                                right_left_and_right.delete(key_to_remove);
-                                            }
-                                        },
-                                    }
-                                } else if boxed_node_left.key == key_to_delete {
-                                    match &mut **boxed_node_left {
-                                        Node {
-                                            key: key_left_only,
-                                            value: value_left_only,
-                                            left: left_left_only,
-                                            right: right_left_only,
-                                        } => match (&mut *left_left_only, &mut *right_left_only) {
-                                            (Edge { node: None }, Edge { node: None }) => {
-                                                *key = key_left_only.clone();
-                                                *value = value_left_only.clone();
-                                                *left = Edge { node: None };
-                                            }
-                                            (
-                                                Edge {
-                                                    node: Some(boxed_node_left),
-                                                },
-                                                Edge { node: None },
-                                            ) => {
-                                                *key = key_left_only.clone();
-                                                *value = value_left_only.clone();
-                                                let key_to_remove = boxed_node_left.key.clone();
-                                                left_left_only.delete(key_to_remove);
-                                            }
-                                            (
-                                                Edge { node: None },
-                                                Edge {
-                                                    node: Some(boxed_node_right),
-                                                },
-                                            ) => {
-                                                *key = key_left_only.clone();
-                                                *value = value_left_only.clone();
-                                                let key_to_remove = boxed_node_right.key.clone();
-                                                right_left_only.delete(key_to_remove);
-                                            }
-                                            (
-                                                Edge {
-                                                    node: Some(_boxed_node_left),
-                                                },
-                                                Edge {
-                                                    node: Some(boxed_node_right),
-                                                },
-                                            ) => {
-                                                *key = key_left_only.clone();
-                                                *value = value_left_only.clone();
-                                                let key_to_remove = boxed_node_right.key.clone();
-                                                right_left_only.delete(key_to_remove);
-                                            }
-                                        },
-                                    }
-                                }
-                            }
-                        },
-                        Ordering::Greater => right.delete(key_to_delete),
-                        Ordering::Less => left.delete(key_to_delete),
-                    },
-                },
-            },
+                        pending.push_back(Pending::Entry(handle));
+                        upper = node.right;
+                    }
+                }
+                break;
+            }
+        }
+        pending
+    }
+
+    /// Number of keys in `range`. Without subtree sizes in the nodes,
+    /// this has to visit them.
+    pub fn count_range<R: RangeBounds<K>>(&self, range: R) -> usize {
+        self.range(range).count()
+    }
+
+    /// Removes the entries with keys in `range`, and returns how many
+    /// there were. Subtrees that lie entirely in the range are freed
+    /// whole, and only the topmost node in the range is replaced by its
+    /// successor, so this takes O(height + removed).
+    ///
+    /// The walk goes down to the topmost node in `range`. The keys in
+    /// range left of it are the largest of its left subtree, and those
+    /// right of it the smallest of its right subtree, so each side is
+    /// trimmed along a single path.
+    pub fn remove_range<R: RangeBounds<K>>(&mut self, range: R) -> usize {
+        let mut place = Place::Root;
+        while let Some(handle) = self.link(place) {
+            let key = &self.pool.get(handle).key;
+            if below(key, &range) {
+                place = Place::Right(handle);
+            } else if above(key, &range) {
+                place = Place::Left(handle);
+            } else {
+                let removed = self.remove_largest(Place::Left(handle), &range)
+                    + self.remove_smallest(Place::Right(handle), &range);
+                self.unlink(place);
+                return removed + 1;
+            }
+        }
+        0
+    }
+
+    /// Removes the keys that are not below `range` from the subtree at
+    /// `place`, whose keys all come before its end.
+    fn remove_largest<R: RangeBounds<K>>(&mut self, mut place: Place<K, V>, range: &R) -> usize {
+        let mut removed = 0;
+        while let Some(handle) = self.link(place) {
+            if below(&self.pool.get(handle).key, range) {
+                place = Place::Right(handle);
+            } else {
+                let node = self.pool.free(handle);
+                removed += 1 + self.clear(node.right);
+                self.set_link(place, node.left);
+            }
+        }
+        removed
+    }
+
+    /// Removes the keys that are not above `range` from the subtree at
+    /// `place`, whose keys all come after its start.
+    fn remove_smallest<R: RangeBounds<K>>(&mut self, mut place: Place<K, V>, range: &R) -> usize {
+        let mut removed = 0;
+        while let Some(handle) = self.link(place) {
+            if above(&self.pool.get(handle).key, range) {
+                place = Place::Left(handle);
+            } else {
+                let node = self.pool.free(handle);
+                removed += 1 + self.clear(node.left);
+                self.set_link(place, node.right);
+            }
+        }
+        removed
+    }
+}
+
+/// Whether `key` comes before every key in `range`.
+fn below<K: Ord, R: RangeBounds<K>>(key: &K, range: &R) -> bool {
+    match range.start_bound() {
+        Bound::Included(start) => key < start,
+        Bound::Excluded(start) => key <= start,
+        Bound::Unbounded => false,
+    }
+}
+
+/// Whether `key` comes after every key in `range`.
+fn above<K: Ord, R: RangeBounds<K>>(key: &K, range: &R) -> bool {
+    match range.end_bound() {
+        Bound::Included(end) => key > end,
+        Bound::Excluded(end) => key >= end,
+        Bound::Unbounded => false,
+    }
+}
+
+/// Part of the in-order sequence still to visit: a whole subtree, or
+/// a single node whose subtrees were queued apart.
+enum Pending<K, V> {
+    Tree(Handle<Node<K, V>>),
+    Entry(Handle<Node<K, V>>),
+}
+
+/// The in-order sequence of a whole tree, not split up yet.
+fn whole_tree<K, V>(root: Link<K, V>) -> ArrayDeque<Pending<K, V>> {
+    let mut pending = ArrayDeque::new();
+    if let Some(root) = root {
+        pending.push_back(Pending::Tree(root));
+    }
+    pending
+}
+
+/// Splits the subtree at the front of `pending` until a single node
+/// comes up, and returns that node. `children` reads the links of a
+/// node, which is all the splitting needs.
+fn next_front<K, V>(
+    pending: &mut ArrayDeque<Pending<K, V>>,
+    mut children: impl FnMut(Handle<Node<K, V>>) -> (Link<K, V>, Link<K, V>),
+) -> Option<Handle<Node<K, V>>> {
+    loop {
+        match pending.pop_front()? {
+            Pending::Entry(handle) => return Some(handle),
+            Pending::Tree(handle) => {
+                let (left, right) = children(handle);
+                if let Some(child) = right {
+                    pending.push_front(Pending::Tree(child));
+                }
+                pending.push_front(Pending::Entry(handle));
+                if let Some(child) = left {
+                    pending.push_front(Pending::Tree(child));
+                }
+            }
+        }
+    }
+}
+
+/// Like `next_front`, from the back.
+fn next_back<K, V>(
+    pending: &mut ArrayDeque<Pending<K, V>>,
+    mut children: impl FnMut(Handle<Node<K, V>>) -> (Link<K, V>, Link<K, V>),
+) -> Option<Handle<Node<K, V>>> {
+    loop {
+        match pending.pop_back()? {
+            Pending::Entry(handle) => return Some(handle),
+            Pending::Tree(handle) => {
+                let (left, right) = children(handle);
+                if let Some(child) = left {
+                    pending.push_back(Pending::Tree(child));
+                }
+                pending.push_back(Pending::Entry(handle));
+                if let Some(child) = right {
+                    pending.push_back(Pending::Tree(child));
+                }
+            }
+        }
+    }
+}
+
+fn children<K, V>(
+    pool: &NodePool<Node<K, V>>,
+    handle: Handle<Node<K, V>>,
+) -> (Link<K, V>, Link<K, V>) {
+    let node = pool.get(handle);
+    (node.left, node.right)
+}
+
+/// In-order iterator. The deque holds the rest of the sequence as
+/// subtrees and single nodes; each end splits its subtree until a
+/// single node comes up, so both ends meet without counting.
+pub struct Iter<'a, K, V> {
+    pool: &'a NodePool<Node<K, V>>,
+    pending: ArrayDeque<Pending<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pool = self.pool;
+        let node = pool.get(next_front(&mut self.pending, |handle| {
+            children(pool, handle)
+        })?);
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let pool = self.pool;
+        let node = pool.get(next_back(&mut self.pending, |handle| {
+            children(pool, handle)
+        })?);
+        Some((&node.key, &node.value))
+    }
+}
+
+/// In-order iterator with mutable values. A node is lent out once it
+/// comes up as a single entry, which happens once per node. Every node
+/// is reached through `get_ptr`, as borrowing the pool itself would
+/// also borrow the values lent out before.
+pub struct IterMut<'a, K, V> {
+    pool: &'a mut NodePool<Node<K, V>>,
+    pending: ArrayDeque<Pending<K, V>>,
+    marker: PhantomData<&'a mut V>,
+}
+
+impl<'a, K, V> IterMut<'a, K, V> {
+    /// Links of a node that was not lent out yet.
+    fn children(
+        pool: &mut NodePool<Node<K, V>>,
+        handle: Handle<Node<K, V>>,
+    ) -> (Link<K, V>, Link<K, V>) {
+        // SAFETY: a node is split before it comes up as an entry, so it
+        // was not lent out, and `get_ptr` touches no other node.
+        let node = unsafe { &*pool.get_ptr(handle) };
+        (node.left, node.right)
+    }
+
+    /// # Safety
+    ///
+    /// `handle` must not have been lent out before.
+    unsafe fn lend(&mut self, handle: Handle<Node<K, V>>) -> (&'a K, &'a mut V) {
+        // SAFETY: `get_ptr` leaves the nodes lent out before untouched,
+        // and the pool is borrowed for `'a`, so no node is freed in the
+        // meantime.
+        let node = unsafe { &mut *self.pool.get_ptr(handle) };
+        (&node.key, &mut node.value)
+    }
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pool = &mut *self.pool;
+        let handle = next_front(&mut self.pending, |handle| Self::children(pool, handle))?;
+        // SAFETY: every node comes up as an entry only once.
+        Some(unsafe { self.lend(handle) })
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let pool = &mut *self.pool;
+        let handle = next_back(&mut self.pending, |handle| Self::children(pool, handle))?;
+        // SAFETY: every node comes up as an entry only once.
+        Some(unsafe { self.lend(handle) })
+    }
+}
+
+pub struct PreOrder<'a, K, V> {
+    pool: &'a NodePool<Node<K, V>>,
+    pending: Stack<Handle<Node<K, V>>>,
+}
+
+impl<'a, K, V> Iterator for PreOrder<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.pool.get(self.pending.pop()?);
+        self.pending.extend(node.right);
+        self.pending.extend(node.left);
+        Some((&node.key, &node.value))
+    }
+}
+
+/// Nodes are pushed unexpanded, and pushed again with their children
+/// on top of them the first time they come up.
+pub struct PostOrder<'a, K, V> {
+    pool: &'a NodePool<Node<K, V>>,
+    pending: Stack<(Handle<Node<K, V>>, bool)>,
+}
+
+impl<'a, K, V> Iterator for PostOrder<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let handle = next_post_order(self.pool, &mut self.pending)?;
+        let node = self.pool.get(handle);
+        Some((&node.key, &node.value))
+    }
+}
+
+/// Pops `pending` until an expanded node comes up, expanding the others.
+fn next_post_order<K, V>(
+    pool: &NodePool<Node<K, V>>,
+    pending: &mut Stack<(Handle<Node<K, V>>, bool)>,
+) -> Option<Handle<Node<K, V>>> {
+    loop {
+        let (handle, expanded) = pending.pop()?;
+        if expanded {
+            return Some(handle);
+        }
+        let node = pool.get(handle);
+        pending.push((handle, true));
+        pending.extend(node.right.map(|right| (right, false)));
+        pending.extend(node.left.map(|left| (left, false)));
+    }
+}
+
+pub struct LevelOrder<'a, K, V> {
+    pool: &'a NodePool<Node<K, V>>,
+    pending: Queue<Handle<Node<K, V>>>,
+}
+
+impl<'a, K, V> Iterator for LevelOrder<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.pool.get(self.pending.dequeue()?);
+        self.pending.extend(node.left);
+        self.pending.extend(node.right);
+        Some((&node.key, &node.value))
+    }
+}
+
+/// Owning in-order iterator. Like `Iter`, but each node is freed as it
+/// comes up; the nodes left over go with the pool.
+pub struct IntoIter<K, V> {
+    pool: NodePool<Node<K, V>>,
+    pending: ArrayDeque<Pending<K, V>>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let pool = &mut self.pool;
+        let handle = next_front(&mut self.pending, |handle| children(pool, handle))?;
+        let node = pool.free(handle);
+        Some((node.key, node.value))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<(K, V)> {
+        let pool = &mut self.pool;
+        let handle = next_back(&mut self.pending, |handle| children(pool, handle))?;
+        let node = pool.free(handle);
+        Some((node.key, node.value))
+    }
+}
+
+pub struct IntoPreOrder<K, V> {
+    pool: NodePool<Node<K, V>>,
+    pending: Stack<Handle<Node<K, V>>>,
+}
+
+impl<K, V> Iterator for IntoPreOrder<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let node = self.pool.free(self.pending.pop()?);
+        self.pending.extend(node.right);
+        self.pending.extend(node.left);
+        Some((node.key, node.value))
+    }
+}
+
+/// Like `PostOrder`; a node is freed once its children were yielded.
+pub struct IntoPostOrder<K, V> {
+    pool: NodePool<Node<K, V>>,
+    pending: Stack<(Handle<Node<K, V>>, bool)>,
+}
+
+impl<K, V> Iterator for IntoPostOrder<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let node = self
+            .pool
+            .free(next_post_order(&self.pool, &mut self.pending)?);
+        Some((node.key, node.value))
+    }
+}
+
+pub struct IntoLevelOrder<K, V> {
+    pool: NodePool<Node<K, V>>,
+    pending: Queue<Handle<Node<K, V>>>,
+}
+
+impl<K, V> Iterator for IntoLevelOrder<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let node = self.pool.free(self.pending.dequeue()?);
+        self.pending.extend(node.left);
+        self.pending.extend(node.right);
+        Some((node.key, node.value))
+    }
+}
+
+impl<K, V> IntoIterator for BinarySearchTree<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter {
+            pending: whole_tree(self.root),
+            pool: self.pool,
         }
     }
 }
 
+impl<'a, K, V> IntoIterator for &'a BinarySearchTree<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut BinarySearchTree<K, V> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_util::XorShift;
     use pretty_assertions::assert_eq;
 
+    /// Entries in pre-order, which fixes the shape of a search tree.
+    fn shape<K: Clone, V: Clone>(bt: &BinarySearchTree<K, V>) -> Vec<(K, V)> {
+        bt.pre_order()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
     #[test]
     fn test_insert_nodes() {
-        let mut bt = BinarySearchTree {
-            root: Some(Edge::from((1, "asd"))),
-        };
+        let mut bt = BinarySearchTree::new();
+        bt.insert(1, "asd");
         bt.insert(2, "BLA");
         bt.insert(0, "123");
         bt.insert(15, "@@@");
         bt.insert(2, "BLAS");
         assert_eq!(
-            bt,
-            BinarySearchTree {
-                root: Some(Edge {
-                    node: Some(Box::new(Node {
-                        key: 1,
-                        value: "asd",
-                        left: Edge {
-                            node: Some(Box::new(Node {
-                                key: 0,
-                                value: "123",
-                                left: Edge { node: None },
-                                right: Edge { node: None }
-                            }))
-                        },
-                        right: Edge {
-                            node: Some(Box::new(Node {
-                                key: 2,
-                                value: "BLAS",
-                                left: Edge { node: None },
-                                right: Edge {
-                                    node: Some(Box::new(Node {
-                                        key: 15,
-                                        value: "@@@",
-                                        left: Edge { node: None },
-                                        right: Edge { node: None }
-                                    }))
-                                },
-                            }))
-                        },
-                    })),
-                }),
-            }
+            shape(&bt),
+            [(1, "asd"), (0, "123"), (2, "BLAS"), (15, "@@@")]
         );
+        assert_eq!(bt.level_order().nth(2), Some((&2, &"BLAS")));
     }
+
     #[test]
     fn test_delete_nodes() {
-        let mut bt = BinarySearchTree {
-            root: Some(Edge::from((1, "asd"))),
-        };
+        let mut bt = BinarySearchTree::new();
+        bt.insert(1, "asd");
         bt.insert(2, "BLA");
         bt.insert(0, "123");
         bt.insert(-1, "123");
         bt.insert(15, "@@@");
         bt.insert(2, "BLAS");
-        bt.delete(-1);
-        bt.delete(2);
-        bt.delete(15);
-        bt.delete(1);
-        assert_eq!(
-            bt,
-            BinarySearchTree {
-                root: Some(Edge {
-                    node: Some(Box::new(Node {
-                        key: 0,
-                        value: "123",
-                        left: Edge { node: None },
-                        right: Edge { node: None }
-                    }))
-                }),
-            }
-        );
+        bt.delete(&-1);
+        bt.delete(&2);
+        bt.delete(&15);
+        bt.delete(&1);
+        assert_eq!(shape(&bt), [(0, "123")]);
+        bt.check_invariants();
     }
 
     #[test]
     fn test_find_nodes() {
-        let bt = BinarySearchTree {
-            root: Some(Edge {
-                node: Some(Box::new(Node {
-                    key: 0,
-                    value: "123",
-                    left: Edge { node: None },
-                    right: Edge { node: None },
-                })),
-            }),
-        };
+        let mut bt = BinarySearchTree::new();
+        bt.insert(0, "123");
         assert_eq!(bt.find(0), Some(&"123"));
         assert_eq!(bt.find(1), None);
     }
+
+    #[test]
+    fn test_deleted_nodes_are_recycled() {
+        let mut bt = sample();
+        let blocks = bt.pool.stats().blocks;
+        for round in 0..100 {
+            bt.delete(&(round % 7));
+            bt.insert(round % 7, round);
+        }
+        assert_eq!(bt.pool.stats().blocks, blocks);
+        bt.check_invariants();
+
+        let mut bt = BinarySearchTree::new();
+        for key in 0..1_000 {
+            bt.insert(key, key);
+        }
+        assert_eq!(bt.remove_range(10..), 990);
+        bt.shrink_to_fit();
+        assert_eq!(bt.pool.stats().blocks, 1);
+        assert_eq!(keys(bt.iter()), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_min_max() {
+        let mut bt = BinarySearchTree::new();
+        assert_eq!(bt.min(), None);
+        for key in [5, 3, 8, 1, 4, 9].iter() {
+            bt.insert(*key, key * 10);
+        }
+        assert_eq!(bt.min(), Some((&1, &10)));
+        assert_eq!(bt.max(), Some((&9, &90)));
+    }
+
+    fn sample() -> BinarySearchTree<i32, i32> {
+        let mut bt = BinarySearchTree::new();
+        for key in [5, 3, 8, 1, 4, 7, 9].iter() {
+            bt.insert(*key, key * 10);
+        }
+        bt
+    }
+
+    fn keys<'a>(iter: impl Iterator<Item = (&'a i32, &'a i32)>) -> Vec<i32> {
+        iter.map(|(key, _)| *key).collect()
+    }
+
+    #[test]
+    fn test_in_order_from_both_ends() {
+        let bt = sample();
+        assert_eq!(keys(bt.iter()), [1, 3, 4, 5, 7, 8, 9]);
+        assert_eq!(keys(bt.iter().rev()), [9, 8, 7, 5, 4, 3, 1]);
+        let mut iter = bt.iter();
+        assert_eq!(iter.next(), Some((&1, &10)));
+        assert_eq!(iter.next_back(), Some((&9, &90)));
+        assert_eq!(iter.next_back(), Some((&8, &80)));
+        assert_eq!(keys(iter.by_ref()), [3, 4, 5, 7]);
+        assert_eq!(iter.next_back(), None);
+        drop(iter);
+        assert_eq!(keys((&BinarySearchTree::<i32, i32>::new()).into_iter()), []);
+
+        let mut owned = bt.into_iter();
+        assert_eq!(owned.next_back(), Some((9, 90)));
+        assert_eq!(owned.next(), Some((1, 10)));
+        let rest: Vec<_> = owned.map(|(key, _)| key).collect();
+        assert_eq!(rest, [3, 4, 5, 7, 8]);
+    }
+
+    #[test]
+    fn test_pre_post_and_level_order() {
+        let bt = sample();
+        assert_eq!(keys(bt.pre_order()), [5, 3, 1, 4, 8, 7, 9]);
+        assert_eq!(keys(bt.post_order()), [1, 4, 3, 7, 9, 8, 5]);
+        assert_eq!(keys(bt.level_order()), [5, 3, 8, 1, 4, 7, 9]);
+        let owned = |iter: &mut dyn Iterator<Item = (i32, i32)>| -> Vec<i32> {
+            iter.map(|(key, _)| key).collect()
+        };
+        assert_eq!(
+            owned(&mut bt.clone().into_pre_order()),
+            [5, 3, 1, 4, 8, 7, 9]
+        );
+        assert_eq!(
+            owned(&mut bt.clone().into_post_order()),
+            [1, 4, 3, 7, 9, 8, 5]
+        );
+        assert_eq!(owned(&mut bt.into_level_order()), [5, 3, 8, 1, 4, 7, 9]);
+    }
+
+    #[test]
+    fn test_both_ends_meet_on_random_trees() {
+        let mut rng = XorShift::new(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..50 {
+            let mut bt = BinarySearchTree::new();
+            let mut model = std::collections::BTreeMap::new();
+            for _ in 0..rng.below(200) {
+                let key = rng.below(500);
+                bt.insert(key, ());
+                model.insert(key, ());
+            }
+            let (mut iter, mut owned) = (bt.iter(), bt.clone().into_iter());
+            let mut expected = model.into_iter();
+            loop {
+                let from_back = rng.below(2) == 0;
+                let want = if from_back {
+                    expected.next_back()
+                } else {
+                    expected.next()
+                };
+                if from_back {
+                    assert_eq!(iter.next_back().map(|(key, _)| *key), want.map(|e| e.0));
+                    assert_eq!(owned.next_back(), want);
+                } else {
+                    assert_eq!(iter.next().map(|(key, _)| *key), want.map(|e| e.0));
+                    assert_eq!(owned.next(), want);
+                }
+                if want.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_range_mut_and_iter_mut() {
+        let mut bt = sample();
+        for (_, value) in bt.range_mut(3..=7) {
+            *value += 1;
+        }
+        let mut iter = bt.iter_mut();
+        *iter.next_back().unwrap().1 = 0;
+        drop(iter);
+        let values: Vec<_> = bt.iter().map(|(_, value)| *value).collect();
+        assert_eq!(values, [10, 31, 41, 51, 71, 80, 0]);
+        assert_eq!(bt.range_mut(6..7).next(), None);
+        assert_eq!(bt.count_range(..5), 3);
+        assert_eq!(bt.count_range(10..), 0);
+    }
+
+    #[test]
+    fn test_range_queries_match_btree_map() {
+        let mut rng = XorShift::new(0x2545_f491_4f6c_dd1d);
+        let mut next = move |n: u64| rng.below(n) as i32;
+        let bound = |next: &mut dyn FnMut(u64) -> i32| match next(3) {
+            0 => Bound::Included(next(120)),
+            1 => Bound::Excluded(next(120)),
+            _ => Bound::Unbounded,
+        };
+        for _ in 0..200 {
+            let mut bt = BinarySearchTree::new();
+            let mut model = std::collections::BTreeMap::new();
+            for _ in 0..next(60) {
+                let key = next(100);
+                bt.insert(key, key);
+                model.insert(key, key);
+            }
+            let (start, end) = (bound(&mut next), bound(&mut next));
+            let range = match (start, end) {
+                (
+                    Bound::Included(a) | Bound::Excluded(a),
+                    Bound::Included(b) | Bound::Excluded(b),
+                ) if a > b => (end, start),
+                _ => (start, end),
+            };
+            let expected: Vec<_> = model.range(range).map(|(key, _)| *key).collect();
+            assert_eq!(keys(bt.range(range)), expected);
+            let reversed: Vec<_> = expected.iter().rev().copied().collect();
+            assert_eq!(keys(bt.range(range).rev()), reversed);
+            assert_eq!(bt.count_range(range), expected.len());
+            assert_eq!(bt.remove_range(range), expected.len());
+            model.retain(|key, _| !range.contains(key));
+            assert_eq!(keys(bt.iter()), model.keys().copied().collect::<Vec<_>>());
+        }
+    }
+
+    /// Tree of `keys` where every node hangs on the same side of its
+    /// parent, as sorted insertions build, but put together in linear
+    /// time.
+    fn spine(keys: core::ops::Range<i32>, right: bool) -> BinarySearchTree<i32, i32> {
+        let mut bt = BinarySearchTree::new();
+        if right {
+            for key in keys.rev() {
+                let node = Node {
+                    right: bt.root,
+                    ..Node::from((key, key))
+                };
+                bt.root = Some(bt.pool.alloc(node));
+            }
+        } else {
+            for key in keys {
+                let node = Node {
+                    left: bt.root,
+                    ..Node::from((key, key))
+                };
+                bt.root = Some(bt.pool.alloc(node));
+            }
+        }
+        bt
+    }
+
+    #[test]
+    fn test_remove_range_on_degenerate_trees() {
+        const N: i32 = 200_000;
+        let expected: Vec<_> = (0..5).chain(N - 5..N).collect();
+        for &right in [true, false].iter() {
+            let mut bt = spine(0..N, right);
+            assert_eq!(bt.remove_range(5..N - 5), N as usize - 10);
+            assert_eq!(keys(bt.iter()), expected);
+        }
+
+        // The whole left subtree, a long spine, is freed at once.
+        let mut bt = spine(0..N, true);
+        let node = Node {
+            left: bt.root,
+            ..Node::from((N, N))
+        };
+        bt.root = Some(bt.pool.alloc(node));
+        assert_eq!(bt.remove_range(..), N as usize + 1);
+        assert_eq!(bt, BinarySearchTree::new());
+    }
+
+    #[test]
+    fn test_sorted_inserts_and_drop_on_degenerate_trees() {
+        const N: i32 = 5_000;
+        let mut ascending = BinarySearchTree::new();
+        let mut descending = BinarySearchTree::new();
+        for key in 0..N {
+            ascending.insert(key, key);
+            descending.insert(N - 1 - key, key);
+        }
+        assert_eq!(ascending.max(), Some((&(N - 1), &(N - 1))));
+        assert_eq!(descending.min(), Some((&0, &(N - 1))));
+        drop(ascending);
+        drop(descending);
+
+        drop(spine(0..200_000, true));
+    }
+
+    #[test]
+    fn test_delete_returns_entry() {
+        let mut bt = sample();
+        // Both children are there, and neither holds the key.
+        assert_eq!(bt.delete(&5), Some((5, 50)));
+        assert_eq!(bt.delete(&5), None);
+        assert_eq!(bt.root.map(|root| bt.pool.get(root).key), Some(7));
+        assert_eq!(bt.delete(&3), Some((3, 30)));
+        bt.check_invariants();
+        assert_eq!(keys(bt.iter()), [1, 4, 7, 8, 9]);
+        for key in [1, 4, 7, 8, 9].iter() {
+            assert_eq!(bt.delete(key), Some((*key, key * 10)));
+        }
+        assert_eq!(bt, BinarySearchTree::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "keys out of order")]
+    fn test_check_invariants_catches_misplaced_key() {
+        let mut bt = sample();
+        if let Some(root) = bt.root {
+            bt.pool.get_mut(root).key = 2;
+        }
+        bt.check_invariants();
+    }
+
+    #[test]
+    fn test_matches_btree_map() {
+        let mut rng = XorShift::new(0xdead_beef_cafe_f00d);
+        let mut next = move |n: u64| rng.below(n) as i32;
+        for _ in 0..100 {
+            let mut bt = BinarySearchTree::new();
+            let mut model = std::collections::BTreeMap::new();
+            for i in 0..300 {
+                let key = next(64);
+                match next(3) {
+                    0 => assert_eq!(bt.delete(&key), model.remove_entry(&key)),
+                    1 => assert_eq!(bt.get(&key), model.get(&key)),
+                    _ => {
+                        bt.insert(key, i);
+                        model.insert(key, i);
+                    }
+                }
+                bt.check_invariants();
+            }
+            let entries: Vec<_> = bt.into_iter().collect();
+            assert_eq!(entries, model.into_iter().collect::<Vec<_>>());
+        }
+    }
 }
@@ -0,0 +1,200 @@
+//! Hazard pointers (Michael, 2004) for the lock-free structures.
+//!
+//! A thread that is about to dereference a shared node first publishes
+//! its address in one of the hazard slots of its `Record`, then checks
+//! that the node is still reachable. Removed nodes are not freed right
+//! away but retired: they are only reclaimed once no hazard slot points
+//! to them.
+//!
+//! Records are never freed before the `Domain` itself. A `Guard` holds
+//! a record exclusively for the duration of one operation, which is
+//! what gives it sole access to the record's list of retired nodes.
+
+use alloc_crate::boxed::Box;
+use alloc_crate::vec::Vec;
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{fence, AtomicBool, AtomicPtr, Ordering};
+
+/// Hazard slots per record; the Michael–Scott queue needs two.
+pub(crate) const HAZARDS: usize = 2;
+
+/// Retired nodes a record accumulates before scanning the hazards.
+const SCAN_THRESHOLD: usize = 64;
+
+pub(crate) struct Domain {
+    records: AtomicPtr<Record>,
+}
+
+struct Record {
+    next: *mut Record,
+    active: AtomicBool,
+    hazards: [AtomicPtr<u8>; HAZARDS],
+    retired: UnsafeCell<Vec<Retired>>,
+}
+
+struct Retired {
+    ptr: *mut u8,
+    reclaim: unsafe fn(*mut u8),
+}
+
+pub(crate) struct Guard<'a> {
+    domain: &'a Domain,
+    record: &'a Record,
+}
+
+unsafe fn reclaim_box<T>(ptr: *mut u8) {
+    drop(Box::from_raw(ptr as *mut T));
+}
+
+impl Domain {
+    pub(crate) const fn new() -> Self {
+        Domain {
+            records: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Acquires an inactive record, or publishes a new one if every
+    /// record is in use.
+    pub(crate) fn guard(&self) -> Guard<'_> {
+        let mut record = self.records.load(Ordering::Acquire);
+        while !record.is_null() {
+            // SAFETY: records are only freed when the domain is dropped.
+            let candidate = unsafe { &*record };
+            if !candidate.active.load(Ordering::Relaxed)
+                && candidate
+                    .active
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return Guard {
+                    domain: self,
+                    record: candidate,
+                };
+            }
+            record = candidate.next;
+        }
+        let record = Box::into_raw(Box::new(Record {
+            next: ptr::null_mut(),
+            active: AtomicBool::new(true),
+            hazards: Default::default(),
+            retired: UnsafeCell::new(Vec::new()),
+        }));
+        let mut head = self.records.load(Ordering::Relaxed);
+        loop {
+            // SAFETY: the record is not shared until the exchange succeeds.
+            unsafe { (*record).next = head };
+            match self.records.compare_exchange_weak(
+                head,
+                record,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+        Guard {
+            domain: self,
+            // SAFETY: records are only freed when the domain is dropped.
+            record: unsafe { &*record },
+        }
+    }
+
+    /// Reclaims the retired nodes no hazard slot points to.
+    fn scan(&self, retired: &mut Vec<Retired>) {
+        // Pairs with the fence in `Guard::protect`: either the scan sees
+        // the hazard, or the protecting thread sees the node unlinked.
+        fence(Ordering::SeqCst);
+        let mut hazards = Vec::new();
+        let mut record = self.records.load(Ordering::Acquire);
+        while !record.is_null() {
+            // SAFETY: records are only freed when the domain is dropped.
+            let current = unsafe { &*record };
+            for hazard in current.hazards.iter() {
+                let ptr = hazard.load(Ordering::Acquire);
+                if !ptr.is_null() {
+                    hazards.push(ptr);
+                }
+            }
+            record = current.next;
+        }
+        hazards.sort_unstable();
+        retired.retain(|node| {
+            if hazards.binary_search(&node.ptr).is_ok() {
+                return true;
+            }
+            // SAFETY: the node was unlinked before being retired and no
+            // thread has it protected, so no thread can reach it anymore.
+            unsafe { (node.reclaim)(node.ptr) };
+            false
+        });
+    }
+}
+
+impl Drop for Domain {
+    fn drop(&mut self) {
+        let mut record = *self.records.get_mut();
+        while !record.is_null() {
+            // SAFETY: with `&mut self` no guard is alive, so every record
+            // and every retired node is exclusively ours.
+            let Record { next, retired, .. } = *unsafe { Box::from_raw(record) };
+            for node in retired.into_inner() {
+                unsafe { (node.reclaim)(node.ptr) };
+            }
+            record = next;
+        }
+    }
+}
+
+// SAFETY: the records are only mutated through atomics, except for the
+// retired lists, which are only touched by the guard holding the record.
+unsafe impl Send for Domain {}
+unsafe impl Sync for Domain {}
+
+impl Guard<'_> {
+    /// Loads `source` and publishes it in hazard slot `slot`, retrying
+    /// until `source` still holds the published pointer. The returned
+    /// node cannot be reclaimed until the slot is overwritten or the
+    /// guard is dropped.
+    pub(crate) fn protect<T>(&self, slot: usize, source: &AtomicPtr<T>) -> *mut T {
+        let mut ptr = source.load(Ordering::Relaxed);
+        loop {
+            self.record.hazards[slot].store(ptr as *mut u8, Ordering::Relaxed);
+            fence(Ordering::SeqCst);
+            let current = source.load(Ordering::Acquire);
+            if current == ptr {
+                return ptr;
+            }
+            ptr = current;
+        }
+    }
+
+    /// Hands over a node that is no longer reachable from the shared
+    /// structure, to be freed once no hazard points to it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Box::into_raw`, must already be unlinked,
+    /// and must not be retired twice.
+    pub(crate) unsafe fn retire<T>(&self, ptr: *mut T) {
+        // SAFETY: the guard holds the record exclusively.
+        let retired = &mut *self.record.retired.get();
+        retired.push(Retired {
+            ptr: ptr as *mut u8,
+            reclaim: reclaim_box::<T>,
+        });
+        if retired.len() >= SCAN_THRESHOLD {
+            self.domain.scan(retired);
+        }
+    }
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        for hazard in self.record.hazards.iter() {
+            hazard.store(ptr::null_mut(), Ordering::Release);
+        }
+        self.record.active.store(false, Ordering::Release);
+    }
+}
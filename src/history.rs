@@ -0,0 +1,285 @@
+//! Undo and redo on top of two stacks of commands.
+
+use crate::chapter_one::stack::Stack;
+use alloc_crate::vec;
+use alloc_crate::vec::Vec;
+
+/// Reversible change to a state of type `S`.
+pub trait Command<S> {
+    fn apply(&self, state: &mut S);
+
+    /// The command that undoes this one.
+    fn invert(&self) -> Self;
+}
+
+/// State together with the commands that led to it.
+///
+/// Applied commands are pushed on the undo stack; undoing one moves it
+/// to the redo stack and redoing moves it back. Applying a new command
+/// after undoing discards the redo stack: the undone branch is lost.
+///
+/// Commands applied between `begin_group` and `end_group` are recorded
+/// as a single step. With a limit, only the most recent steps can be
+/// undone. The steps past the limit sit at the bottom of the undo stack
+/// until it holds twice the limit, and are then cut off together, so
+/// each step costs O(1) amortized to drop.
+pub struct History<S, Op> {
+    state: S,
+    undo: Stack<Vec<Op>>,
+    redo: Stack<Vec<Op>>,
+    /// Steps on top of `undo` that can still be undone.
+    undoable: usize,
+    limit: Option<usize>,
+    group: Option<Vec<Op>>,
+    depth: usize,
+}
+
+impl<S, Op: Command<S>> History<S, Op> {
+    pub fn new(state: S) -> Self {
+        History {
+            state,
+            undo: Stack::new(),
+            redo: Stack::new(),
+            undoable: 0,
+            limit: None,
+            group: None,
+            depth: 0,
+        }
+    }
+
+    /// Keeps at most `limit` steps to undo, letting go of the oldest one
+    /// when a new step would exceed it.
+    pub fn with_limit(state: S, limit: usize) -> Self {
+        History {
+            limit: Some(limit),
+            ..History::new(state)
+        }
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub fn into_state(self) -> S {
+        self.state
+    }
+
+    /// Steps that `undo` can revert.
+    pub fn undo_len(&self) -> usize {
+        self.undoable
+    }
+
+    /// Steps that `redo` can reapply.
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    pub fn apply(&mut self, command: Op) {
+        command.apply(&mut self.state);
+        self.redo = Stack::new();
+        match self.group.as_mut() {
+            Some(group) => group.push(command),
+            None => self.record(vec![command]),
+        }
+    }
+
+    /// Starts recording the commands applied from now on as one step.
+    /// Groups may be nested; only the outermost one makes a step.
+    pub fn begin_group(&mut self) {
+        if self.depth == 0 {
+            self.group = Some(Vec::new());
+        }
+        self.depth += 1;
+    }
+
+    /// Panics if no group was begun.
+    pub fn end_group(&mut self) {
+        assert!(self.depth > 0, "no group to end");
+        self.depth -= 1;
+        if self.depth == 0 {
+            self.close_group();
+        }
+    }
+
+    /// Reverts the last step. Commands already applied in an open group
+    /// are recorded as a step first, so they are what gets reverted; the
+    /// group stays open and still has to be ended. Returns whether there
+    /// was a step to revert.
+    pub fn undo(&mut self) -> bool {
+        self.close_group();
+        if self.depth > 0 {
+            self.group = Some(Vec::new());
+        }
+        if self.undoable == 0 {
+            return false;
+        }
+        let step = self.undo.pop().expect("undoable steps are on the stack");
+        for command in step.iter().rev() {
+            command.invert().apply(&mut self.state);
+        }
+        self.redo.push(step);
+        self.undoable -= 1;
+        true
+    }
+
+    /// Reapplies the last undone step. Returns whether there was one.
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(step) => {
+                for command in step.iter() {
+                    command.apply(&mut self.state);
+                }
+                self.undo.push(step);
+                self.undoable += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn close_group(&mut self) {
+        if let Some(group) = self.group.take() {
+            if !group.is_empty() {
+                self.record(group);
+            }
+        }
+    }
+
+    fn record(&mut self, step: Vec<Op>) {
+        self.undo.push(step);
+        self.undoable += 1;
+        if let Some(limit) = self.limit {
+            self.undoable = self.undoable.min(limit);
+            if self.undo.len() > 2 * limit {
+                self.undo.truncate(self.undoable);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    enum Edit {
+        Insert(usize, char),
+        Delete(usize, char),
+    }
+
+    impl Command<String> for Edit {
+        fn apply(&self, text: &mut String) {
+            match *self {
+                Edit::Insert(at, c) => text.insert(at, c),
+                Edit::Delete(at, c) => assert_eq!(text.remove(at), c),
+            }
+        }
+
+        fn invert(&self) -> Self {
+            match *self {
+                Edit::Insert(at, c) => Edit::Delete(at, c),
+                Edit::Delete(at, c) => Edit::Insert(at, c),
+            }
+        }
+    }
+
+    fn typed(text: &str) -> History<String, Edit> {
+        let mut history = History::new(String::new());
+        for (at, c) in text.chars().enumerate() {
+            history.apply(Edit::Insert(at, c));
+        }
+        history
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut history = typed("abc");
+        assert!(history.undo());
+        assert!(history.undo());
+        assert_eq!(history.state(), "a");
+        assert!(history.redo());
+        assert_eq!(history.state(), "ab");
+        assert_eq!((history.undo_len(), history.redo_len()), (2, 1));
+        assert!(history.undo());
+        assert!(history.undo());
+        assert!(!history.undo());
+        assert_eq!(history.state(), "");
+        while history.redo() {}
+        assert_eq!(history.into_state(), "abc");
+    }
+
+    #[test]
+    fn test_new_command_truncates_redo_branch() {
+        let mut history = typed("abc");
+        history.undo();
+        history.apply(Edit::Delete(0, 'a'));
+        assert_eq!(history.state(), "b");
+        assert!(!history.redo());
+        history.undo();
+        history.undo();
+        assert_eq!(history.state(), "a");
+    }
+
+    #[test]
+    fn test_groups_undo_as_one_step() {
+        let mut history = typed("ab");
+        history.begin_group();
+        history.apply(Edit::Insert(2, 'c'));
+        history.begin_group();
+        history.apply(Edit::Delete(0, 'a'));
+        history.end_group();
+        history.apply(Edit::Insert(0, 'x'));
+        history.end_group();
+        assert_eq!(history.state(), "xbc");
+        assert_eq!(history.undo_len(), 3);
+        history.undo();
+        assert_eq!(history.state(), "ab");
+        history.redo();
+        assert_eq!(history.state(), "xbc");
+    }
+
+    #[test]
+    fn test_undo_inside_group() {
+        let mut history = typed("ab");
+        history.begin_group();
+        history.apply(Edit::Insert(2, 'c'));
+        history.undo();
+        history.end_group();
+        assert_eq!(history.state(), "ab");
+        assert_eq!((history.undo_len(), history.redo_len()), (2, 1));
+
+        // The group goes on recording after the undo.
+        history.begin_group();
+        history.apply(Edit::Insert(2, 'c'));
+        history.undo();
+        history.apply(Edit::Insert(0, 'x'));
+        history.apply(Edit::Insert(0, 'y'));
+        history.end_group();
+        assert_eq!(history.state(), "yxab");
+        assert_eq!(history.undo_len(), 3);
+        history.undo();
+        assert_eq!(history.state(), "ab");
+    }
+
+    #[test]
+    fn test_limit_drops_oldest_steps() {
+        let mut history = History::with_limit(String::new(), 3);
+        for (at, c) in "abcde".chars().enumerate() {
+            history.apply(Edit::Insert(at, c));
+        }
+        assert_eq!(history.undo_len(), 3);
+        while history.undo() {}
+        assert_eq!(history.state(), "ab");
+        while history.redo() {}
+        assert_eq!(history.state(), "abcde");
+
+        // Steps past the limit are cut off once they pile up.
+        for at in 5..20 {
+            history.apply(Edit::Insert(at, 'z'));
+            assert!(history.undo.len() <= 6);
+        }
+        assert_eq!(history.undo_len(), 3);
+        while history.undo() {}
+        assert_eq!(history.state().len(), 17);
+    }
+}
@@ -1,2 +1,25 @@
+//! Data structures from _Advanced Data Structures_, by Peter Brass.
+//!
+//! The structures are grouped by kind: [`linear`] holds stacks, queues
+//! and deques, [`search`] holds search trees. Each group comes with
+//! traits so that code can be written against the kind of structure
+//! and swap implementations. [`history`] builds undo and redo on top of
+//! the stacks.
+//!
+//! Without the default `std` feature the crate is `no_std` and only
+//! needs `alloc`; the extras that need threads or the file system are
+//! left out.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc as alloc_crate;
+
+pub mod alloc;
 mod chapter_one;
 mod chapter_two;
+mod hazard;
+pub mod history;
+pub mod linear;
+pub mod search;
+#[cfg(test)]
+mod test_util;
@@ -0,0 +1,165 @@
+//! Stacks, queues and double-ended queues (Brass, chapter 1).
+
+pub use crate::chapter_one::atomic_queue::{self, AtomicQueue};
+pub use crate::chapter_one::atomic_stack::{self, AtomicStack};
+#[cfg(feature = "std")]
+pub use crate::chapter_one::bounded_queue::SyncBoundedQueue;
+pub use crate::chapter_one::bounded_queue::{self, BoundedQueue, OverflowPolicy};
+#[cfg(feature = "std")]
+pub use crate::chapter_one::channel::{self, Receiver, SendError, Sender, TrySendError};
+pub use crate::chapter_one::chase_lev::{self, Steal, Stealer, Worker};
+pub use crate::chapter_one::deque::{self, ArrayDeque, LinkedDeque};
+#[cfg(feature = "std")]
+pub use crate::chapter_one::file_queue::{self, FileQueue, FileQueueOptions, SyncPolicy};
+pub use crate::chapter_one::fixed::{self, ArrayQueue, ArrayStack};
+pub use crate::chapter_one::monoid::{self, Gcd, Max, Min, Monoid, MonoidQueue, MonoidStack, Sum};
+pub use crate::chapter_one::multi_stack::{self, MultiStack};
+pub use crate::chapter_one::persistent_queue::{self, PersistentQueue};
+pub use crate::chapter_one::persistent_stack::{
+    self, ArcPointer, PersistentStack, PointerKind, RcPointer,
+};
+pub use crate::chapter_one::queue::{self, LinkedQueue, Queue};
+pub use crate::chapter_one::shadow_queue::{self, ShadowQueue};
+pub use crate::chapter_one::shadow_stack::{self, ShadowStack};
+pub use crate::chapter_one::stack::{self, Stack};
+
+/// Last in, first out: `pop` returns the most recently pushed element.
+pub trait Lifo<T> {
+    fn push(&mut self, element: T);
+
+    fn pop(&mut self) -> Option<T>;
+
+    /// The element `pop` would return.
+    fn peek(&self) -> Option<&T>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// First in, first out: `dequeue` returns the least recently enqueued
+/// element.
+pub trait Fifo<T> {
+    fn enqueue(&mut self, element: T);
+
+    fn dequeue(&mut self) -> Option<T>;
+
+    /// The element `dequeue` would return.
+    fn peek(&self) -> Option<&T>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+macro_rules! impl_lifo {
+    ($type:ident, $push:ident, $pop:ident, $peek:ident) => {
+        impl<T> Lifo<T> for $type<T> {
+            fn push(&mut self, element: T) {
+                $type::$push(self, element)
+            }
+
+            fn pop(&mut self) -> Option<T> {
+                $type::$pop(self)
+            }
+
+            fn peek(&self) -> Option<&T> {
+                $type::$peek(self)
+            }
+
+            fn len(&self) -> usize {
+                $type::len(self)
+            }
+        }
+    };
+}
+
+macro_rules! impl_fifo {
+    ($type:ident, $enqueue:ident, $dequeue:ident, $peek:ident) => {
+        impl<T> Fifo<T> for $type<T> {
+            fn enqueue(&mut self, element: T) {
+                $type::$enqueue(self, element)
+            }
+
+            fn dequeue(&mut self) -> Option<T> {
+                $type::$dequeue(self)
+            }
+
+            fn peek(&self) -> Option<&T> {
+                $type::$peek(self)
+            }
+
+            fn len(&self) -> usize {
+                $type::len(self)
+            }
+        }
+    };
+}
+
+impl_lifo!(Stack, push, pop, peek);
+impl_lifo!(ShadowStack, push, pop, peek);
+impl_lifo!(ArrayDeque, push_back, pop_back, back);
+impl_lifo!(LinkedDeque, push_back, pop_back, back);
+
+impl_fifo!(Queue, enqueue, dequeue, peek);
+impl_fifo!(LinkedQueue, enqueue, dequeue, peek);
+impl_fifo!(ShadowQueue, enqueue, dequeue, peek);
+impl_fifo!(ArrayDeque, push_back, pop_front, front);
+impl_fifo!(LinkedDeque, push_back, pop_front, front);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn reverse<S: Lifo<u32> + Default>(elements: &[u32]) -> Vec<u32> {
+        let mut stack = S::default();
+        for &element in elements {
+            stack.push(element);
+        }
+        assert_eq!(stack.peek(), elements.last());
+        let mut reversed = Vec::new();
+        while let Some(element) = stack.pop() {
+            reversed.push(element);
+        }
+        assert!(stack.is_empty());
+        reversed
+    }
+
+    fn pass_through<Q: Fifo<u32> + Default>(elements: &[u32]) -> Vec<u32> {
+        let mut queue = Q::default();
+        for &element in elements {
+            queue.enqueue(element);
+        }
+        assert_eq!(queue.peek(), elements.first());
+        assert_eq!(queue.len(), elements.len());
+        let mut passed = Vec::new();
+        while let Some(element) = queue.dequeue() {
+            passed.push(element);
+        }
+        passed
+    }
+
+    #[test]
+    fn test_lifo_implementations() {
+        let elements: Vec<u32> = (0..100).collect();
+        let expected: Vec<u32> = (0..100).rev().collect();
+        assert_eq!(reverse::<Stack<u32>>(&elements), expected);
+        assert_eq!(reverse::<ShadowStack<u32>>(&elements), expected);
+        assert_eq!(reverse::<ArrayDeque<u32>>(&elements), expected);
+        assert_eq!(reverse::<LinkedDeque<u32>>(&elements), expected);
+    }
+
+    #[test]
+    fn test_fifo_implementations() {
+        let elements: Vec<u32> = (0..100).collect();
+        assert_eq!(pass_through::<Queue<u32>>(&elements), elements);
+        assert_eq!(pass_through::<LinkedQueue<u32>>(&elements), elements);
+        assert_eq!(pass_through::<ShadowQueue<u32>>(&elements), elements);
+        assert_eq!(pass_through::<ArrayDeque<u32>>(&elements), elements);
+        assert_eq!(pass_through::<LinkedDeque<u32>>(&elements), elements);
+    }
+}
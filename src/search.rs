@@ -0,0 +1,72 @@
+//! Search trees (Brass, chapter 2).
+
+pub use crate::chapter_two::multi_map::{self, BinarySearchMultiMap};
+pub use crate::chapter_two::search_tree::{self, BinarySearchTree};
+
+/// Map from keys to values that keeps its keys sorted.
+pub trait OrderedMap<K, V> {
+    /// Inserts `value` under `key`, replacing any previous value.
+    fn insert(&mut self, key: K, value: V);
+
+    fn get(&self, key: &K) -> Option<&V>;
+
+    /// Removes `key` and returns its value.
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Entry with the smallest key.
+    fn min(&self) -> Option<(&K, &V)>;
+
+    /// Entry with the largest key.
+    fn max(&self) -> Option<(&K, &V)>;
+}
+
+impl<K: Ord, V> OrderedMap<K, V> for BinarySearchTree<K, V> {
+    fn insert(&mut self, key: K, value: V) {
+        BinarySearchTree::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        BinarySearchTree::get(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.delete(key).map(|(_, value)| value)
+    }
+
+    fn min(&self) -> Option<(&K, &V)> {
+        BinarySearchTree::min(self)
+    }
+
+    fn max(&self) -> Option<(&K, &V)> {
+        BinarySearchTree::max(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fill<M: OrderedMap<u32, String> + Default>(keys: &[u32]) -> M {
+        let mut map = M::default();
+        for &key in keys {
+            map.insert(key, key.to_string());
+        }
+        map
+    }
+
+    #[test]
+    fn test_binary_search_tree_as_ordered_map() {
+        let mut map: BinarySearchTree<u32, String> = fill(&[5, 2, 8, 1, 9]);
+        assert_eq!(OrderedMap::get(&map, &8), Some(&"8".to_string()));
+        assert!(map.contains_key(&1));
+        assert_eq!(OrderedMap::remove(&mut map, &1), Some("1".to_string()));
+        assert_eq!(OrderedMap::remove(&mut map, &1), None);
+        assert!(!map.contains_key(&1));
+        assert_eq!(OrderedMap::min(&map), Some((&2, &"2".to_string())));
+        assert_eq!(OrderedMap::max(&map), Some((&9, &"9".to_string())));
+    }
+}
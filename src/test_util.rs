@@ -0,0 +1,57 @@
+//! Helpers shared by the tests.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Xorshift generator (Marsaglia, 2003): deterministic, so randomized
+/// tests fail the same way every time.
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    /// Panics if `seed` is zero, which xorshift never leaves.
+    pub(crate) fn new(seed: u64) -> Self {
+        assert!(seed != 0, "xorshift needs a non-zero seed");
+        XorShift(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Number in `0..n`.
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+/// Hands out tokens and counts how many are still alive, to check that
+/// a structure drops exactly the elements it still holds.
+#[derive(Default)]
+pub(crate) struct DropCounter(Arc<AtomicUsize>);
+
+#[derive(Debug)]
+pub(crate) struct Token(Arc<AtomicUsize>);
+
+impl DropCounter {
+    pub(crate) fn new() -> Self {
+        DropCounter::default()
+    }
+
+    pub(crate) fn token(&self) -> Token {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Token(Arc::clone(&self.0))
+    }
+
+    pub(crate) fn alive(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}